@@ -0,0 +1,292 @@
+use std::f32::consts::TAU;
+use std::fmt;
+use std::str::FromStr;
+
+// shape of the tone produced while the sound timer is active
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+    Noise,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 5] = [
+        Waveform::Square,
+        Waveform::Sine,
+        Waveform::Triangle,
+        Waveform::Sawtooth,
+        Waveform::Noise,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Waveform::Square => "square",
+            Waveform::Sine => "sine",
+            Waveform::Triangle => "triangle",
+            Waveform::Sawtooth => "sawtooth",
+            Waveform::Noise => "noise",
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Waveform::ALL
+            .into_iter()
+            .find(|wave| wave.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Waveform::ALL.iter().map(Waveform::name).collect();
+                format!(
+                    "Unknown waveform '{s}', expected one of: {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+// Settings of the buzzer, shared by every frontend so all of them sound the same
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuzzerConfig {
+    pub waveform: Waveform,
+    pub frequency: f32, // in hertz
+    pub volume: f32,    // between 0.0 and 1.0
+    pub attack: f32,    // seconds to fade in when the buzzer turns on
+    pub release: f32,   // seconds to fade out when the buzzer turns off
+}
+
+impl Default for BuzzerConfig {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            volume: 0.25,
+            attack: 0.002,
+            release: 0.002,
+        }
+    }
+}
+
+// Sample generator for the buzzer
+pub struct Buzzer {
+    config: BuzzerConfig,
+    sample_rate: u32,
+    phase: f32,
+    active: bool,
+    envelope: f32,
+    noise_state: u16,
+    noise_value: f32,
+}
+
+impl Buzzer {
+    pub fn new(config: BuzzerConfig, sample_rate: u32) -> Self {
+        Self {
+            config,
+            sample_rate,
+            phase: 0.0,
+            active: false,
+            envelope: 0.0,
+            noise_state: 0xACE1,
+            noise_value: 1.0,
+        }
+    }
+
+    pub fn config(&self) -> &BuzzerConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: BuzzerConfig) {
+        self.config = config;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    // next sample of the raw waveform, in the range -1.0..=1.0,
+    // without applying the volume nor the envelope
+    pub fn next_wave_sample(&mut self) -> f32 {
+        let phase = self.phase;
+        let sample = match self.config.waveform {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Noise => self.noise_value,
+        };
+
+        let phase_inc = self.config.frequency / self.sample_rate as f32;
+        let next_phase = phase + phase_inc;
+        // the noise changes its value twice per period, so the frequency
+        // still controls how "high" it sounds
+        if self.config.waveform == Waveform::Noise
+            && (next_phase >= 1.0 || (phase < 0.5 && next_phase >= 0.5))
+        {
+            self.noise_value = self.next_noise();
+        }
+        self.phase = next_phase % 1.0;
+        sample
+    }
+
+    // next sample with the volume and the attack/release envelope applied
+    pub fn next_sample(&mut self) -> f32 {
+        self.step_envelope();
+        if self.envelope == 0.0 {
+            // keep the oscillator running so the phase stays continuous
+            self.next_wave_sample();
+            return 0.0;
+        }
+        self.next_wave_sample() * self.envelope * self.config.volume
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = self.next_sample();
+        }
+    }
+
+    fn step_envelope(&mut self) {
+        let (target, duration) = if self.active {
+            (1.0, self.config.attack)
+        } else {
+            (0.0, self.config.release)
+        };
+
+        if duration <= 0.0 {
+            self.envelope = target;
+            return;
+        }
+
+        let step = 1.0 / (duration * self.sample_rate as f32);
+        self.envelope = if self.envelope < target {
+            (self.envelope + step).min(target)
+        } else {
+            (self.envelope - step).max(target)
+        };
+    }
+
+    // 16 bit galois LFSR, deterministic so recordings are reproducible
+    fn next_noise(&mut self) -> f32 {
+        let lsb = self.noise_state & 1;
+        self.noise_state >>= 1;
+        if lsb != 0 {
+            self.noise_state ^= 0xB400;
+            1.0
+        } else {
+            -1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a square wave slow enough to stay high, so the samples show the envelope
+    fn envelope_buzzer(attack: f32, release: f32) -> Buzzer {
+        let config = BuzzerConfig {
+            waveform: Waveform::Square,
+            frequency: 1.0,
+            volume: 0.5,
+            attack,
+            release,
+        };
+        Buzzer::new(config, 1000)
+    }
+
+    fn assert_close(samples: &[f32], expected: &[f32]) {
+        assert_eq!(samples.len(), expected.len());
+        for (sample, expected) in samples.iter().zip(expected) {
+            assert!(
+                (sample - expected).abs() < 1e-4,
+                "{samples:?} != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn attack_ramps_linearly() {
+        // 10 samples of attack at 1000hz
+        let mut buzzer = envelope_buzzer(0.01, 0.0);
+        buzzer.set_active(true);
+        let mut samples = [0.0; 12];
+        buzzer.fill(&mut samples);
+        let expected: Vec<f32> = (1..=12).map(|n| n.min(10) as f32 * 0.05).collect();
+        assert_close(&samples, &expected);
+    }
+
+    #[test]
+    fn release_ramps_linearly() {
+        let mut buzzer = envelope_buzzer(0.0, 0.005);
+        buzzer.set_active(true);
+        assert_close(&[buzzer.next_sample()], &[0.5]);
+        buzzer.set_active(false);
+        let mut samples = [0.0; 7];
+        buzzer.fill(&mut samples);
+        assert_close(&samples, &[0.4, 0.3, 0.2, 0.1, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn volume_zero_is_silent() {
+        for waveform in Waveform::ALL {
+            let config = BuzzerConfig {
+                waveform,
+                volume: 0.0,
+                ..BuzzerConfig::default()
+            };
+            let mut buzzer = Buzzer::new(config, 44100);
+            buzzer.set_active(true);
+            let mut samples = [1.0; 1000];
+            buzzer.fill(&mut samples);
+            assert!(samples.iter().all(|&sample| sample == 0.0), "{waveform}");
+        }
+    }
+
+    #[test]
+    fn waveforms_stay_within_the_volume() {
+        for waveform in Waveform::ALL {
+            let config = BuzzerConfig {
+                waveform,
+                volume: 0.3,
+                ..BuzzerConfig::default()
+            };
+            let mut buzzer = Buzzer::new(config, 44100);
+            buzzer.set_active(true);
+            let mut samples = [0.0; 4410];
+            buzzer.fill(&mut samples);
+            assert!(
+                samples.iter().all(|sample| sample.abs() <= 0.3 + 1e-6),
+                "{waveform}"
+            );
+            // past the attack the wave reaches the volume
+            let peak = samples
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            assert!(peak > 0.29, "{waveform} peaks at {peak}");
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use crate::disasm::{self, Instruction, Line};
+use crate::RAM_START_ADDR;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// How the code leaves a basic block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    // runs into the next block, which something else jumps to
+    Next(u16),
+    Jump(u16),
+    // the called subroutine and the address it returns to
+    Call { target: u16, ret: u16 },
+    // a skip instruction, `skip` is run when the condition holds
+    Skip { next: u16, skip: u16 },
+    Return,
+    // Bnnn, the target is nnn plus V0, so it is unknown
+    Computed(u16),
+    // an opcode that isn't a chip-8 instruction or the end of the rom
+    Stop,
+}
+
+impl Exit {
+    // the blocks run after this one in the same subroutine, a call
+    // continues at the return address
+    pub fn successors(&self) -> Vec<u16> {
+        match *self {
+            Exit::Next(next) | Exit::Jump(next) => vec![next],
+            Exit::Call { ret, .. } => vec![ret],
+            Exit::Skip { next, skip } => vec![next, skip],
+            Exit::Return | Exit::Computed(_) | Exit::Stop => Vec::new(),
+        }
+    }
+}
+
+// Instructions always run one after the other, from the first to the exit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: u16,
+    pub lines: Vec<Line>,
+    pub exit: Exit,
+}
+
+impl Block {
+    // address after the last instruction
+    pub fn end(&self) -> u16 {
+        self.start + self.lines.len() as u16 * 2
+    }
+}
+
+// The blocks reached from the entry of a subroutine without following the
+// calls. The main program is the one at 0x200.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subroutine {
+    pub entry: u16,
+    pub blocks: BTreeSet<u16>,
+    // false for the main loop and the subroutines that never return
+    pub returns: bool,
+}
+
+// The control flow graph of the code reachable from the start of the rom.
+// Code only reached through Bnnn can't be found, the blocks that jump
+// there end with Exit::Computed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<u16, Block>,
+    // by entry address
+    pub subroutines: BTreeMap<u16, Subroutine>,
+}
+
+// the exit of the instruction, None if it doesn't end a block
+fn exit_of(line: &Line) -> Option<Exit> {
+    let next = line.address + 2;
+    use Instruction::*;
+    let exit = match line.instruction {
+        Some(Jp(nnn)) => Exit::Jump(nnn),
+        Some(Call(nnn)) => Exit::Call {
+            target: nnn,
+            ret: next,
+        },
+        Some(SeByte(..) | SneByte(..) | SeReg(..) | SneReg(..) | Skp(_) | Sknp(_)) => Exit::Skip {
+            next,
+            skip: next + 2,
+        },
+        Some(Ret) => Exit::Return,
+        Some(JpV0(nnn)) => Exit::Computed(nnn),
+        Some(_) => return None,
+        None => Exit::Stop,
+    };
+    Some(exit)
+}
+
+impl Cfg {
+    pub fn build(rom: &[u8]) -> Cfg {
+        let reachable = disasm::reachable(rom);
+        // decoded at any reachable address, code jumped to at odd addresses
+        // isn't aligned with the linear disassembly
+        let line_at = |address: u16| {
+            if !reachable.contains(&address) {
+                return None;
+            }
+            let offset = address as usize - RAM_START_ADDR;
+            let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+            Some(Line {
+                address,
+                opcode,
+                instruction: Instruction::decode(opcode),
+            })
+        };
+
+        // the first instruction of every block
+        let mut leaders = BTreeSet::from([RAM_START_ADDR as u16]);
+        for &address in &reachable {
+            let Some(exit) = line_at(address).as_ref().and_then(exit_of) else {
+                continue;
+            };
+            leaders.extend(exit.successors());
+            if let Exit::Call { target, .. } = exit {
+                leaders.insert(target);
+            }
+        }
+        leaders.retain(|address| reachable.contains(address));
+
+        let mut blocks = BTreeMap::new();
+        for &start in &leaders {
+            let mut block_lines = Vec::new();
+            let mut address = start;
+            let exit = loop {
+                let Some(line) = line_at(address) else {
+                    break Exit::Stop;
+                };
+                block_lines.push(line);
+                if let Some(exit) = exit_of(&line) {
+                    break exit;
+                }
+                address += 2;
+                if leaders.contains(&address) {
+                    break Exit::Next(address);
+                }
+            };
+            blocks.insert(
+                start,
+                Block {
+                    start,
+                    lines: block_lines,
+                    exit,
+                },
+            );
+        }
+
+        let entries =
+            std::iter::once(RAM_START_ADDR as u16).chain(blocks.values().filter_map(|block| {
+                match block.exit {
+                    Exit::Call { target, .. } => Some(target),
+                    _ => None,
+                }
+            }));
+        let mut subroutines = BTreeMap::new();
+        for entry in entries {
+            if blocks.contains_key(&entry) && !subroutines.contains_key(&entry) {
+                subroutines.insert(entry, subroutine(&blocks, entry));
+            }
+        }
+
+        Cfg {
+            blocks,
+            subroutines,
+        }
+    }
+
+    // the blocks ending with Bnnn, their targets aren't in the graph
+    pub fn computed_jumps(&self) -> impl Iterator<Item = &Block> {
+        self.blocks
+            .values()
+            .filter(|block| matches!(block.exit, Exit::Computed(_)))
+    }
+
+    // Graphviz source of the graph, the subroutines are drawn as clusters:
+    //   chip8 cfg rom.ch8 | dot -Tsvg > rom.svg
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        _ = writeln!(dot, "digraph rom {{");
+        _ = writeln!(dot, "  node [shape=box, fontname=\"monospace\"];");
+
+        // a block shared by subroutines is drawn in the first one
+        let mut drawn = BTreeSet::new();
+        for subroutine in self.subroutines.values() {
+            let name = if subroutine.entry == RAM_START_ADDR as u16 {
+                "main".to_string()
+            } else {
+                format!("sub 0x{:03X}", subroutine.entry)
+            };
+            _ = writeln!(dot, "  subgraph cluster_{:03X} {{", subroutine.entry);
+            _ = writeln!(dot, "    label=\"{name}\";");
+            for start in &subroutine.blocks {
+                if drawn.insert(*start) {
+                    _ = writeln!(dot, "    {}", dot_node(&self.blocks[start]));
+                }
+            }
+            _ = writeln!(dot, "  }}");
+        }
+        for block in self.blocks.values() {
+            if drawn.insert(block.start) {
+                _ = writeln!(dot, "  {}", dot_node(block));
+            }
+        }
+
+        for block in self.blocks.values() {
+            let from = block.start;
+            // the targets outside of the rom have no block
+            let edge = |dot: &mut String, to: u16, attributes: &str| {
+                if self.blocks.contains_key(&to) {
+                    _ = writeln!(dot, "  b{from:03X} -> b{to:03X} [{attributes}];");
+                }
+            };
+            match block.exit {
+                Exit::Next(next) => edge(&mut dot, next, ""),
+                Exit::Jump(target) => edge(&mut dot, target, "label=\"jp\""),
+                Exit::Call { target, ret } => {
+                    edge(&mut dot, target, "label=\"call\", style=dashed");
+                    edge(&mut dot, ret, "label=\"ret\", style=dotted");
+                }
+                Exit::Skip { next, skip } => {
+                    edge(&mut dot, next, "label=\"no\"");
+                    edge(&mut dot, skip, "label=\"skip\"");
+                }
+                Exit::Computed(nnn) => {
+                    _ = writeln!(
+                        dot,
+                        "  unknown_{from:03X} [label=\"0x{nnn:03X} + V0\", shape=diamond];"
+                    );
+                    _ = writeln!(
+                        dot,
+                        "  b{from:03X} -> unknown_{from:03X} [label=\"?\", style=dashed];"
+                    );
+                }
+                Exit::Return | Exit::Stop => (),
+            }
+        }
+        _ = writeln!(dot, "}}");
+        dot
+    }
+}
+
+fn dot_node(block: &Block) -> String {
+    // \l ends a left aligned line
+    let label: String = block
+        .lines
+        .iter()
+        .map(|line| format!("{line}\\l"))
+        .collect();
+    format!("b{:03X} [label=\"{label}\"];", block.start)
+}
+
+// the blocks of the subroutine at `entry`, without following the calls
+fn subroutine(blocks: &BTreeMap<u16, Block>, entry: u16) -> Subroutine {
+    let mut found = BTreeSet::new();
+    let mut pending = vec![entry];
+    let mut returns = false;
+    while let Some(start) = pending.pop() {
+        let Some(block) = blocks.get(&start) else {
+            continue;
+        };
+        if !found.insert(start) {
+            continue;
+        }
+        returns |= block.exit == Exit::Return;
+        pending.extend(block.exit.successors());
+    }
+    Subroutine {
+        entry,
+        blocks: found,
+        returns,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the start, the instructions and the exit of every block
+    fn blocks(cfg: &Cfg) -> Vec<(u16, Vec<u16>, Exit)> {
+        cfg.blocks
+            .values()
+            .map(|block| {
+                let opcodes = block.lines.iter().map(|line| line.opcode).collect();
+                (block.start, opcodes, block.exit)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_blocks_at_skip_targets() {
+        let rom = [
+            0x30, 0x01, // 0x200: se v0, 1
+            0x61, 0x01, // 0x202: ld v1, 1
+            0x62, 0x02, // 0x204: ld v2, 2
+            0x12, 0x04, // 0x206: jp 0x204
+        ];
+        assert_eq!(
+            blocks(&Cfg::build(&rom)),
+            [
+                (
+                    0x200,
+                    vec![0x3001],
+                    Exit::Skip {
+                        next: 0x202,
+                        skip: 0x204
+                    }
+                ),
+                (0x202, vec![0x6101], Exit::Next(0x204)),
+                (0x204, vec![0x6202, 0x1204], Exit::Jump(0x204)),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_blocks_at_call_returns() {
+        let rom = [
+            0x22, 0x06, // 0x200: call 0x206
+            0x60, 0x05, // 0x202: ld v0, 5
+            0x12, 0x02, // 0x204: jp 0x202
+            0x70, 0x01, // 0x206: add v0, 1
+            0x00, 0xEE, // 0x208: ret
+        ];
+        assert_eq!(
+            blocks(&Cfg::build(&rom)),
+            [
+                (
+                    0x200,
+                    vec![0x2206],
+                    Exit::Call {
+                        target: 0x206,
+                        ret: 0x202
+                    }
+                ),
+                (0x202, vec![0x6005, 0x1202], Exit::Jump(0x202)),
+                (0x206, vec![0x7001, 0x00EE], Exit::Return),
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_the_subroutines() {
+        let rom = [
+            0x22, 0x08, // 0x200: call 0x208
+            0x22, 0x0C, // 0x202: call 0x20C
+            0x12, 0x04, // 0x204: jp 0x204
+            0x00, 0x00, // 0x206: padding
+            0x40, 0x00, // 0x208: sne v0, 0
+            0x00, 0xEE, // 0x20A: ret
+            0x12, 0x0C, // 0x20C: jp 0x20C, never returns
+        ];
+        let cfg = Cfg::build(&rom);
+        let subroutines: Vec<_> = cfg
+            .subroutines
+            .values()
+            .map(|sub| {
+                (
+                    sub.entry,
+                    sub.blocks.iter().copied().collect::<Vec<_>>(),
+                    sub.returns,
+                )
+            })
+            .collect();
+        assert_eq!(
+            subroutines,
+            [
+                (0x200, vec![0x200, 0x202, 0x204], false),
+                (0x208, vec![0x208, 0x20A, 0x20C], true),
+                (0x20C, vec![0x20C], false),
+            ]
+        );
+    }
+
+    #[test]
+    fn ends_blocks_at_computed_jumps_and_stops() {
+        let computed = Cfg::build(&[0x60, 0x02, 0xB3, 0x00]);
+        assert_eq!(
+            blocks(&computed),
+            [(0x200, vec![0x6002, 0xB300], Exit::Computed(0x300))]
+        );
+        assert_eq!(computed.computed_jumps().count(), 1);
+
+        // 5xy1 isn't an instruction
+        let unknown = Cfg::build(&[0x60, 0x02, 0x51, 0x21]);
+        assert_eq!(
+            blocks(&unknown),
+            [(0x200, vec![0x6002, 0x5121], Exit::Stop)]
+        );
+
+        // the rom ends without a jump
+        let end = Cfg::build(&[0x60, 0x02, 0x61, 0x03]);
+        assert_eq!(blocks(&end), [(0x200, vec![0x6002, 0x6103], Exit::Stop)]);
+    }
+
+    #[test]
+    fn dot_draws_subroutines_and_edges() {
+        let rom = [
+            0x30, 0x01, // 0x200: se v0, 1
+            0x22, 0x08, // 0x202: call 0x208
+            0xB3, 0x00, // 0x204: jp v0, 0x300
+            0x00, 0x00, // 0x206: padding
+            0x00, 0xEE, // 0x208: ret
+        ];
+        let dot = Cfg::build(&rom).to_dot();
+        assert!(dot.starts_with("digraph rom {\n"));
+        assert!(dot.ends_with("}\n"));
+        for expected in [
+            "subgraph cluster_200 {\n    label=\"main\";",
+            "subgraph cluster_208 {\n    label=\"sub 0x208\";",
+            "b200 -> b202 [label=\"no\"];",
+            "b200 -> b204 [label=\"skip\"];",
+            "b202 -> b208 [label=\"call\", style=dashed];",
+            "b202 -> b204 [label=\"ret\", style=dotted];",
+            "unknown_204 [label=\"0x300 + V0\", shape=diamond];",
+            "b204 -> unknown_204 [label=\"?\", style=dashed];",
+        ] {
+            assert!(dot.contains(expected), "{expected} missing from\n{dot}");
+        }
+        // every block is drawn once, in its subroutine
+        for block in ["b200", "b202", "b204", "b208"] {
+            let node = format!("\n    {block} [label=");
+            assert_eq!(dot.matches(&node).count(), 1, "{block}");
+        }
+    }
+}
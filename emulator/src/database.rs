@@ -0,0 +1,429 @@
+use crate::keymap::Keymap;
+use crate::palette::Palette;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+// a few roms, the test roms of the repository
+const BUNDLED_PROGRAMS: &str = include_str!("../database/programs.json");
+
+// Gamepad inputs bound by the key hints of a rom
+const KEY_HINT_INPUTS: [(&str, &[&str]); 6] = [
+    ("up", &["GamepadDPadUp", "GamepadLeftStickUp"]),
+    ("down", &["GamepadDPadDown", "GamepadLeftStickDown"]),
+    ("left", &["GamepadDPadLeft", "GamepadLeftStickLeft"]),
+    ("right", &["GamepadDPadRight", "GamepadLeftStickRight"]),
+    ("a", &["GamepadA"]),
+    ("b", &["GamepadB"]),
+];
+
+// What is known about a rom, to run it without setting anything by hand
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    // the first platform the rom runs on, None if none is known
+    pub platform: Option<Platform>,
+    // instructions per 60hz frame
+    pub tickrate: Option<u32>,
+    pub quirks: Option<Quirks>,
+    pub palette: Option<Palette>,
+    pub keys: KeyHints,
+}
+
+impl RomInfo {
+    // binds the gamepad to the keys the rom uses to move and act,
+    // the keyboard bindings are left alone
+    pub fn apply_key_hints(&self, keymap: &mut Keymap) {
+        for (hint, inputs) in KEY_HINT_INPUTS {
+            let Some(hex) = self.keys.get(hint) else {
+                continue;
+            };
+            for input in inputs {
+                keymap.bind(input, hex);
+            }
+        }
+    }
+}
+
+// chip-8 keys used by a rom, e.g. { "up": 5, "a": 6 }
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct KeyHints {
+    pub up: Option<u8>,
+    pub down: Option<u8>,
+    pub left: Option<u8>,
+    pub right: Option<u8>,
+    pub a: Option<u8>,
+    pub b: Option<u8>,
+}
+
+impl KeyHints {
+    fn get(&self, hint: &str) -> Option<u8> {
+        let key = match hint {
+            "up" => self.up,
+            "down" => self.down,
+            "left" => self.left,
+            "right" => self.right,
+            "a" => self.a,
+            "b" => self.b,
+            _ => None,
+        };
+        key.filter(|key| *key < 16)
+    }
+}
+
+// Roms by SHA-1, read from files in the format of the programs.json of the
+// chip-8 database (https://github.com/chip-8/chip-8-database):
+//
+//   [{ "title": "...", "authors": ["..."], "roms": { "<sha1>": {
+//       "platforms": ["originalChip8"], "tickrate": 15,
+//       "quirkyPlatforms": { "originalChip8": { "logic": false } },
+//       "colors": { "pixels": ["#000000", "#ffffff"] },
+//       "keys": { "up": 5, "a": 6 } } } }]
+//
+// The other fields of the database are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct Database {
+    roms: HashMap<String, RomInfo>,
+}
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    roms: BTreeMap<String, RomEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+    #[serde(default)]
+    platforms: Vec<String>,
+    tickrate: Option<u32>,
+    #[serde(default)]
+    quirky_platforms: BTreeMap<String, QuirkFlags>,
+    colors: Option<Colors>,
+    #[serde(default)]
+    keys: KeyHints,
+}
+
+// quirks as named by the database, true is the behavior that differs
+// from the modern chip-8
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuirkFlags {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    logic: Option<bool>,
+}
+
+impl QuirkFlags {
+    fn apply(&self, mut quirks: Quirks) -> Quirks {
+        if let Some(shift) = self.shift {
+            quirks.shift_vy = !shift;
+        }
+        if let Some(leave) = self.memory_leave_i_unchanged {
+            quirks.memory_increment_i = !leave;
+        }
+        if self.memory_increment_by_x == Some(true) {
+            quirks.memory_increment_i = true;
+        }
+        if let Some(wrap) = self.wrap {
+            quirks.clip_sprites = !wrap;
+        }
+        if let Some(jump) = self.jump {
+            quirks.jump_vx = jump;
+        }
+        if let Some(logic) = self.logic {
+            quirks.vf_reset = logic;
+        }
+        quirks
+    }
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+impl Database {
+    pub fn bundled() -> Self {
+        let mut database = Database::default();
+        database
+            .extend_from_json(BUNDLED_PROGRAMS)
+            .expect("the bundled database is valid");
+        database
+    }
+
+    pub fn lookup(&self, rom_sha1: &str) -> Option<&RomInfo> {
+        self.roms.get(&rom_sha1.to_ascii_lowercase())
+    }
+
+    // adds the roms of a programs.json, replacing the ones already known
+    pub fn extend_from_json(&mut self, json: &str) -> Result<(), String> {
+        let programs: Vec<Program> =
+            serde_json::from_str(json).map_err(|e| format!("Invalid rom database: {e}"))?;
+        for program in programs {
+            for (sha1, rom) in program.roms {
+                let info = rom_info(&program.title, &program.authors, rom);
+                self.roms.insert(sha1.to_ascii_lowercase(), info);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn rom_info(title: &str, authors: &[String], rom: RomEntry) -> RomInfo {
+    // platforms added to the database after this emulator are skipped
+    let platform = rom
+        .platforms
+        .iter()
+        .find_map(|id| id.parse::<Platform>().ok());
+
+    let quirks = platform.map(|platform| {
+        let quirks = platform.quirks();
+        match rom.quirky_platforms.get(platform.id()) {
+            Some(flags) => flags.apply(quirks),
+            None => quirks,
+        }
+    });
+
+    // only palettes of 2 or 4 colors can be shown, the others are skipped
+    let palette = rom
+        .colors
+        .and_then(|colors| colors.pixels.join(",").parse().ok());
+
+    RomInfo {
+        title: title.to_string(),
+        authors: authors.to_vec(),
+        platform,
+        tickrate: rom
+            .tickrate
+            .filter(|tickrate| *tickrate > 0)
+            .or(platform.map(|platform| platform.default_tickrate())),
+        quirks,
+        palette,
+        keys: rom.keys,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Color;
+
+    // a database with one rom, the fields of the rom entry are given as json
+    fn database(sha1: &str, title: &str, entry: &str) -> Database {
+        let json = format!(r#"[{{"title": "{title}", "roms": {{"{sha1}": {entry}}}}}]"#);
+        let mut database = Database::default();
+        database.extend_from_json(&json).unwrap();
+        database
+    }
+
+    fn flags() -> QuirkFlags {
+        QuirkFlags {
+            shift: None,
+            memory_increment_by_x: None,
+            memory_leave_i_unchanged: None,
+            wrap: None,
+            jump: None,
+            logic: None,
+        }
+    }
+
+    #[test]
+    fn quirk_flags_are_inverted_where_the_names_differ() {
+        let all = Quirks {
+            vf_reset: true,
+            shift_vy: true,
+            memory_increment_i: true,
+            jump_vx: true,
+            clip_sprites: true,
+            ..Quirks::default()
+        };
+        let none = Quirks {
+            vf_reset: false,
+            shift_vy: false,
+            memory_increment_i: false,
+            jump_vx: false,
+            clip_sprites: false,
+            ..Quirks::default()
+        };
+
+        // shift in place is the opposite of shifting vy
+        let shift = QuirkFlags {
+            shift: Some(true),
+            ..flags()
+        };
+        assert!(!shift.apply(all).shift_vy);
+        let shift = QuirkFlags {
+            shift: Some(false),
+            ..flags()
+        };
+        assert!(shift.apply(none).shift_vy);
+
+        // wrapping is the opposite of clipping
+        let wrap = QuirkFlags {
+            wrap: Some(true),
+            ..flags()
+        };
+        assert!(!wrap.apply(all).clip_sprites);
+        let wrap = QuirkFlags {
+            wrap: Some(false),
+            ..flags()
+        };
+        assert!(wrap.apply(none).clip_sprites);
+
+        // leaving I unchanged is the opposite of incrementing it
+        let leave = QuirkFlags {
+            memory_leave_i_unchanged: Some(true),
+            ..flags()
+        };
+        assert!(!leave.apply(all).memory_increment_i);
+        let leave = QuirkFlags {
+            memory_leave_i_unchanged: Some(false),
+            ..flags()
+        };
+        assert!(leave.apply(none).memory_increment_i);
+        // incrementing by x still moves I
+        let both = QuirkFlags {
+            memory_leave_i_unchanged: Some(true),
+            memory_increment_by_x: Some(true),
+            ..flags()
+        };
+        assert!(both.apply(none).memory_increment_i);
+
+        // jump and logic have the same meaning
+        let same = QuirkFlags {
+            jump: Some(true),
+            logic: Some(true),
+            ..flags()
+        };
+        assert_eq!(
+            same.apply(none),
+            Quirks {
+                jump_vx: true,
+                vf_reset: true,
+                ..none
+            }
+        );
+        let same = QuirkFlags {
+            jump: Some(false),
+            logic: Some(false),
+            ..flags()
+        };
+        assert_eq!(
+            same.apply(all),
+            Quirks {
+                jump_vx: false,
+                vf_reset: false,
+                ..all
+            }
+        );
+
+        // flags that aren't given keep the quirks of the platform
+        assert_eq!(flags().apply(all), all);
+        assert_eq!(flags().apply(none), none);
+    }
+
+    #[test]
+    fn quirks_of_the_platform_of_the_rom() {
+        let entry = r#"{"platforms": ["futureChip8", "originalChip8"],
+            "quirkyPlatforms": {"originalChip8": {"shift": true}, "xochip": {"wrap": true}}}"#;
+        let info = database("ab12", "Quirky", entry)
+            .lookup("ab12")
+            .cloned()
+            .unwrap();
+        assert_eq!(info.platform, Some(Platform::OriginalChip8));
+        let expected = Quirks {
+            shift_vy: false,
+            ..Platform::OriginalChip8.quirks()
+        };
+        assert_eq!(info.quirks, Some(expected));
+    }
+
+    #[test]
+    fn lookup_ignores_the_case_of_the_sha1() {
+        let database = database("ABCDEF0123", "Upper", "{}");
+        for sha1 in ["abcdef0123", "ABCDEF0123", "aBcDeF0123"] {
+            assert_eq!(
+                database.lookup(sha1).map(|info| info.title.as_str()),
+                Some("Upper")
+            );
+        }
+        assert!(database.lookup("abcdef").is_none());
+    }
+
+    #[test]
+    fn later_files_replace_earlier_entries() {
+        let mut database = database("ab12", "First", "{}");
+        let json = r#"[{"title": "Second", "roms": {"AB12": {}, "cd34": {}}}]"#;
+        database.extend_from_json(json).unwrap();
+        assert_eq!(database.lookup("ab12").unwrap().title, "Second");
+        assert_eq!(database.lookup("cd34").unwrap().title, "Second");
+    }
+
+    #[test]
+    fn zero_tickrate_falls_back_to_the_platform() {
+        let entry = r#"{"platforms": ["superchip"], "tickrate": 0}"#;
+        let info = database("ab12", "Zero", entry)
+            .lookup("ab12")
+            .cloned()
+            .unwrap();
+        assert_eq!(info.tickrate, Some(Platform::SuperChip.default_tickrate()));
+
+        let entry = r#"{"platforms": ["superchip"], "tickrate": 50}"#;
+        let info = database("ab12", "Fast", entry)
+            .lookup("ab12")
+            .cloned()
+            .unwrap();
+        assert_eq!(info.tickrate, Some(50));
+
+        // without a platform there is nothing to fall back to
+        let info = database("ab12", "Unknown", r#"{"tickrate": 0}"#)
+            .lookup("ab12")
+            .cloned();
+        assert_eq!(info.unwrap().tickrate, None);
+    }
+
+    #[test]
+    fn palettes_need_2_or_4_colors() {
+        let palette = |pixels: &str| {
+            let entry = format!(r#"{{"colors": {{"pixels": {pixels}}}}}"#);
+            database("ab12", "Colors", &entry)
+                .lookup("ab12")
+                .unwrap()
+                .palette
+        };
+        let black = Color::new(0, 0, 0);
+        let white = Color::new(0xFF, 0xFF, 0xFF);
+        assert_eq!(
+            palette(r##"["#000000", "#ffffff"]"##),
+            Some(Palette::two_color(black, white))
+        );
+        assert_eq!(
+            palette(r##"["#000", "#fff", "#aaa", "#555"]"##),
+            Some(Palette::CLASSIC)
+        );
+        assert_eq!(palette(r##"["#000000"]"##), None);
+        assert_eq!(palette(r##"["#000", "#fff", "#aaa"]"##), None);
+        assert_eq!(
+            palette(r##"["#000", "#fff", "#aaa", "#555", "#222"]"##),
+            None
+        );
+        assert_eq!(palette("[]"), None);
+    }
+
+    #[test]
+    fn bundled_database_is_valid() {
+        Database::bundled();
+    }
+}
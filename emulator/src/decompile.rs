@@ -0,0 +1,842 @@
+use crate::cfg::Cfg;
+use crate::disasm::{Instruction, Line};
+use crate::RAM_START_ADDR;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::Range;
+use std::str::FromStr;
+
+// How the pseudo-code is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    // close to the Octo assembly language, but not meant to be assembled
+    Octo,
+    C,
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "octo" => Ok(Style::Octo),
+            "c" => Ok(Style::C),
+            _ => Err(format!("Unknown style '{s}', expected octo or c")),
+        }
+    }
+}
+
+// The second operand of a comparison
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Byte(u8),
+    Reg(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cond {
+    Eq(u8, Operand),
+    Ne(u8, Operand),
+    Key(u8),
+    NotKey(u8),
+}
+
+impl Cond {
+    // the condition a skip instruction skips on
+    fn of_skip(instruction: Instruction) -> Option<Cond> {
+        use Instruction::*;
+        let cond = match instruction {
+            SeByte(x, kk) => Cond::Eq(x, Operand::Byte(kk)),
+            SneByte(x, kk) => Cond::Ne(x, Operand::Byte(kk)),
+            SeReg(x, y) => Cond::Eq(x, Operand::Reg(y)),
+            SneReg(x, y) => Cond::Ne(x, Operand::Reg(y)),
+            Skp(x) => Cond::Key(x),
+            Sknp(x) => Cond::NotKey(x),
+            _ => return None,
+        };
+        Some(cond)
+    }
+
+    fn of_skip_line(line: &Line) -> Option<Cond> {
+        line.instruction.and_then(Cond::of_skip)
+    }
+
+    fn negate(self) -> Cond {
+        match self {
+            Cond::Eq(x, operand) => Cond::Ne(x, operand),
+            Cond::Ne(x, operand) => Cond::Eq(x, operand),
+            Cond::Key(x) => Cond::NotKey(x),
+            Cond::NotKey(x) => Cond::Key(x),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stmt {
+    // any instruction but the jumps and the skips
+    Op(Instruction),
+    If {
+        cond: Cond,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    // runs until a break
+    Loop(Vec<Stmt>),
+    // an opcode that isn't a chip-8 instruction, data or an opcode of another platform
+    Data(u16),
+    Break,
+    Continue,
+    Goto(u16),
+    Label(u16),
+}
+
+// the loop the code being structured is in
+#[derive(Clone, Copy)]
+struct LoopScope {
+    start: u16,
+    // address after the jump closing the loop
+    exit: u16,
+}
+
+// Turns the instructions of a subroutine, in the order of their addresses,
+// into nested statements. The patterns the assemblers and people write are
+// recognized, the rest is left as labels and gotos:
+//   skip; jump else; then...; jump end; else: ...; end:  -> if then else
+//   skip; instruction                                    -> if then
+//   start: ...; jump start                               -> loop
+struct Structurer<'a> {
+    lines: &'a [Line],
+    // addresses that need a label, found by a first pass
+    labels: &'a BTreeSet<u16>,
+    emitted: BTreeSet<u16>,
+    gotos: BTreeSet<u16>,
+}
+
+impl Structurer<'_> {
+    fn end_address(&self, range: &Range<usize>) -> u16 {
+        match self.lines.get(range.end) {
+            Some(line) => line.address,
+            None => self.lines.last().map_or(0, |line| line.address + 2),
+        }
+    }
+
+    // index of the address in the range, or the end of the range
+    fn index_of(&self, range: &Range<usize>, address: u16) -> Option<usize> {
+        if address == self.end_address(range) {
+            return Some(range.end);
+        }
+        self.lines[range.clone()]
+            .binary_search_by_key(&address, |line| line.address)
+            .ok()
+            .map(|idx| range.start + idx)
+    }
+
+    fn jump(&mut self, target: u16, scope: Option<LoopScope>) -> Stmt {
+        match scope {
+            Some(scope) if target == scope.start => Stmt::Continue,
+            Some(scope) if target == scope.exit => Stmt::Break,
+            _ => {
+                self.gotos.insert(target);
+                Stmt::Goto(target)
+            }
+        }
+    }
+
+    // the statement of a single instruction
+    fn single(&mut self, line: &Line, scope: Option<LoopScope>) -> Stmt {
+        match line.instruction {
+            Some(Instruction::Jp(target)) => self.jump(target, scope),
+            Some(instruction) => Stmt::Op(instruction),
+            None => Stmt::Data(line.opcode),
+        }
+    }
+
+    fn structure(&mut self, range: Range<usize>, scope: Option<LoopScope>) -> Vec<Stmt> {
+        let mut stmts = Vec::new();
+        let mut i = range.start;
+        while i < range.end {
+            let line = self.lines[i];
+            if self.labels.contains(&line.address) && self.emitted.insert(line.address) {
+                stmts.push(Stmt::Label(line.address));
+            }
+
+            // the last jump back to here closes a loop
+            let closing = (i + 1..range.end)
+                .rev()
+                .find(|&j| self.lines[j].instruction == Some(Instruction::Jp(line.address)));
+            if let Some(j) = closing.filter(|_| scope.is_none_or(|s| s.start != line.address)) {
+                let inner = LoopScope {
+                    start: line.address,
+                    exit: self.lines[j].address + 2,
+                };
+                // skip; jump start -> leaves the loop when the skip is taken
+                let skip = Cond::of_skip_line(&self.lines[j - 1]).filter(|_| j - 1 > i);
+                let body_end = if skip.is_some() { j - 1 } else { j };
+                let mut body = self.structure(i..body_end, Some(inner));
+                if let Some(cond) = skip {
+                    body.push(Stmt::If {
+                        cond,
+                        then: vec![Stmt::Break],
+                        otherwise: Vec::new(),
+                    });
+                }
+                stmts.push(Stmt::Loop(body));
+                i = j + 1;
+                continue;
+            }
+
+            let Some(cond) = Cond::of_skip_line(&line) else {
+                // a jump over code of another subroutine to what comes next here
+                let falls_through = i + 1 < range.end
+                    && line.instruction == Some(Instruction::Jp(self.lines[i + 1].address));
+                if !falls_through {
+                    stmts.push(self.single(&line, scope));
+                }
+                i += 1;
+                continue;
+            };
+
+            let next = self.lines[range.clone()]
+                .get(i + 1 - range.start)
+                .filter(|next| next.address == line.address + 2)
+                .copied();
+            let is_skip = next.is_some_and(|next| Cond::of_skip_line(&next).is_some());
+            let (Some(next), false) = (next, is_skip) else {
+                // chained skips and skips over code that isn't here
+                stmts.push(Stmt::If {
+                    cond,
+                    then: vec![self.jump(line.address + 4, scope)],
+                    otherwise: Vec::new(),
+                });
+                i += 1;
+                continue;
+            };
+
+            // skip; jump else; then... -> the then part runs when the skip is taken
+            let forward = match next.instruction {
+                Some(Instruction::Jp(target)) if target > next.address + 2 => {
+                    self.index_of(&(i + 2..range.end), target)
+                }
+                _ => None,
+            };
+            let Some(k) = forward else {
+                let then = self.single(&next, scope);
+                stmts.push(Stmt::If {
+                    cond: cond.negate(),
+                    then: vec![then],
+                    otherwise: Vec::new(),
+                });
+                i += 2;
+                continue;
+            };
+
+            // then...; jump end; else: ... -> an else part
+            let else_end = match self.lines[i + 2..k]
+                .last()
+                .and_then(|line| line.instruction)
+            {
+                Some(Instruction::Jp(end)) if k > i + 2 && end > self.end_address(&(i..k)) => {
+                    self.index_of(&(k..range.end), end)
+                }
+                _ => None,
+            };
+            match else_end {
+                Some(m) => {
+                    let then = self.structure(i + 2..k - 1, scope);
+                    let otherwise = self.structure(k..m, scope);
+                    stmts.push(Stmt::If {
+                        cond,
+                        then,
+                        otherwise,
+                    });
+                    i = m;
+                }
+                None => {
+                    let then = self.structure(i + 2..k, scope);
+                    stmts.push(Stmt::If {
+                        cond,
+                        then,
+                        otherwise: Vec::new(),
+                    });
+                    i = k;
+                }
+            }
+        }
+        stmts
+    }
+}
+
+// structured statements of the code of a subroutine
+fn structure(lines: &[Line]) -> Vec<Stmt> {
+    let no_labels = BTreeSet::new();
+    let mut first = Structurer {
+        lines,
+        labels: &no_labels,
+        emitted: BTreeSet::new(),
+        gotos: BTreeSet::new(),
+    };
+    first.structure(0..lines.len(), None);
+
+    // again with labels where the gotos go
+    let labels = first.gotos;
+    let mut second = Structurer {
+        lines,
+        labels: &labels,
+        emitted: BTreeSet::new(),
+        gotos: BTreeSet::new(),
+    };
+    second.structure(0..lines.len(), None)
+}
+
+// Names for the registers used in a single way in the whole rom, e.g. the
+// registers only used as coordinates of sprites. VF is always vf.
+fn register_names(cfg: &Cfg) -> [String; 16] {
+    let mut roles: BTreeMap<u8, BTreeSet<&str>> = BTreeMap::new();
+    for line in cfg.blocks.values().flat_map(|block| &block.lines) {
+        use Instruction::*;
+        let Some(instruction) = line.instruction else {
+            continue;
+        };
+        let mut role = |x: u8, name| _ = roles.entry(x).or_default().insert(name);
+        match instruction {
+            Drw(x, y, _) => {
+                role(x, "x");
+                role(y, "y");
+            }
+            Skp(x) | Sknp(x) | LdVxK(x) => role(x, "key_code"),
+            LdVxDt(x) | LdDtVx(x) => role(x, "time"),
+            LdStVx(x) => role(x, "tone"),
+            LdF(x) => role(x, "digit"),
+            LdB(x) => role(x, "number"),
+            Rnd(x, _) => role(x, "rnd"),
+            _ => (),
+        }
+        // loading, adding and comparing fit any role, the other uses don't
+        match instruction {
+            Or(x, y) | And(x, y) | Xor(x, y) | Shr(x, y) | Subn(x, y) | Shl(x, y) => {
+                role(x, "");
+                role(y, "");
+            }
+            AddI(x) | LdIVx(x) | LdVxI(x) => role(x, ""),
+            _ => (),
+        }
+    }
+
+    let mut names: [String; 16] = std::array::from_fn(|x| format!("v{x:x}"));
+    let mut taken: BTreeMap<&str, usize> = BTreeMap::new();
+    for (x, roles) in roles {
+        let name = match roles.iter().collect::<Vec<_>>()[..] {
+            [name] if !name.is_empty() && x != 0xF => *name,
+            _ => continue,
+        };
+        let count = taken.entry(name).or_default();
+        *count += 1;
+        names[x as usize] = match *count {
+            1 => name.to_string(),
+            n => format!("{name}{n}"),
+        };
+    }
+    names
+}
+
+// the name of a label or a subroutine at the address
+fn label(address: u16) -> String {
+    if address == RAM_START_ADDR as u16 {
+        "main".to_string()
+    } else {
+        format!("label_{address:03X}")
+    }
+}
+
+fn sub_name(address: u16) -> String {
+    if address == RAM_START_ADDR as u16 {
+        "main".to_string()
+    } else {
+        format!("sub_{address:03X}")
+    }
+}
+
+struct Printer<'a> {
+    style: Style,
+    names: &'a [String; 16],
+    out: String,
+}
+
+impl Printer<'_> {
+    fn reg(&self, x: u8) -> &str {
+        &self.names[x as usize & 0xF]
+    }
+
+    fn operand(&self, operand: Operand) -> String {
+        match operand {
+            Operand::Byte(kk) => format!("0x{kk:02X}"),
+            Operand::Reg(y) => self.reg(y).to_string(),
+        }
+    }
+
+    fn cond(&self, cond: Cond) -> String {
+        match (self.style, cond) {
+            (_, Cond::Eq(x, operand)) => format!("{} == {}", self.reg(x), self.operand(operand)),
+            (_, Cond::Ne(x, operand)) => format!("{} != {}", self.reg(x), self.operand(operand)),
+            (Style::Octo, Cond::Key(x)) => format!("{} key", self.reg(x)),
+            (Style::Octo, Cond::NotKey(x)) => format!("{} -key", self.reg(x)),
+            (Style::C, Cond::Key(x)) => format!("key_pressed({})", self.reg(x)),
+            (Style::C, Cond::NotKey(x)) => format!("!key_pressed({})", self.reg(x)),
+        }
+    }
+
+    fn line(&mut self, depth: usize, text: &str) {
+        _ = writeln!(self.out, "{:width$}{text}", "", width = depth * 4);
+    }
+
+    fn op(&self, instruction: Instruction) -> String {
+        use Instruction::*;
+        let r = |x| self.reg(x);
+        match self.style {
+            Style::Octo => match instruction {
+                Sys(nnn) => format!("native 0x{nnn:03X}"),
+                Cls => "clear".to_string(),
+                Ret => "return".to_string(),
+                Jp(nnn) => format!("jump {}", label(nnn)),
+                Call(nnn) => sub_name(nnn),
+                LdByte(x, kk) => format!("{} := 0x{kk:02X}", r(x)),
+                AddByte(x, kk) => format!("{} += 0x{kk:02X}", r(x)),
+                LdReg(x, y) => format!("{} := {}", r(x), r(y)),
+                Or(x, y) => format!("{} |= {}", r(x), r(y)),
+                And(x, y) => format!("{} &= {}", r(x), r(y)),
+                Xor(x, y) => format!("{} ^= {}", r(x), r(y)),
+                AddReg(x, y) => format!("{} += {}", r(x), r(y)),
+                Sub(x, y) => format!("{} -= {}", r(x), r(y)),
+                Shr(x, y) => format!("{} >>= {}", r(x), r(y)),
+                Subn(x, y) => format!("{} =- {}", r(x), r(y)),
+                Shl(x, y) => format!("{} <<= {}", r(x), r(y)),
+                LdI(nnn) => format!("i := 0x{nnn:03X}"),
+                JpV0(nnn) => format!("jump0 0x{nnn:03X}"),
+                Rnd(x, kk) => format!("{} := random 0x{kk:02X}", r(x)),
+                Drw(x, y, n) => format!("sprite {} {} {n}", r(x), r(y)),
+                LdVxDt(x) => format!("{} := delay", r(x)),
+                LdVxK(x) => format!("{} := key", r(x)),
+                LdDtVx(x) => format!("delay := {}", r(x)),
+                LdStVx(x) => format!("buzzer := {}", r(x)),
+                AddI(x) => format!("i += {}", r(x)),
+                LdF(x) => format!("i := hex {}", r(x)),
+                LdB(x) => format!("bcd {}", r(x)),
+                LdIVx(x) => format!("save {}", r(x)),
+                LdVxI(x) => format!("load {}", r(x)),
+                // only the jumps and the skips are left, they are structured
+                skip => format!("# {skip}"),
+            },
+            Style::C => match instruction {
+                Sys(nnn) => format!("native(0x{nnn:03X});"),
+                Cls => "clear();".to_string(),
+                Ret => "return;".to_string(),
+                Jp(nnn) => format!("goto {};", label(nnn)),
+                Call(nnn) => format!("{}();", sub_name(nnn)),
+                LdByte(x, kk) => format!("{} = 0x{kk:02X};", r(x)),
+                AddByte(x, kk) => format!("{} += 0x{kk:02X};", r(x)),
+                LdReg(x, y) => format!("{} = {};", r(x), r(y)),
+                Or(x, y) => format!("{} |= {};", r(x), r(y)),
+                And(x, y) => format!("{} &= {};", r(x), r(y)),
+                Xor(x, y) => format!("{} ^= {};", r(x), r(y)),
+                AddReg(x, y) => format!("{} += {}; // vf = carry", r(x), r(y)),
+                Sub(x, y) => format!("{} -= {}; // vf = no borrow", r(x), r(y)),
+                Subn(x, y) => format!("{0} = {1} - {0}; // vf = no borrow", r(x), r(y)),
+                Shr(x, y) if x == y => format!("{} >>= 1; // vf = bit shifted out", r(x)),
+                Shl(x, y) if x == y => format!("{} <<= 1; // vf = bit shifted out", r(x)),
+                // which register is shifted depends on the shift_vy quirk
+                Shr(x, y) => format!("{} = shr({}, {});", r(x), r(x), r(y)),
+                Shl(x, y) => format!("{} = shl({}, {});", r(x), r(x), r(y)),
+                LdI(nnn) => format!("i = 0x{nnn:03X};"),
+                JpV0(nnn) => format!("jump(0x{nnn:03X} + v0);"),
+                Rnd(x, kk) => format!("{} = rand() & 0x{kk:02X};", r(x)),
+                Drw(x, y, n) => format!("vf = draw({}, {}, {n});", r(x), r(y)),
+                LdVxDt(x) => format!("{} = delay;", r(x)),
+                LdVxK(x) => format!("{} = wait_key();", r(x)),
+                LdDtVx(x) => format!("delay = {};", r(x)),
+                LdStVx(x) => format!("buzzer = {};", r(x)),
+                AddI(x) => format!("i += {};", r(x)),
+                LdF(x) => format!("i = font({});", r(x)),
+                LdB(x) => format!("bcd({});", r(x)),
+                LdIVx(x) => format!("save(v0..{});", r(x)),
+                LdVxI(x) => format!("load(v0..{});", r(x)),
+                skip => format!("// {skip}"),
+            },
+        }
+    }
+
+    fn stmts(&mut self, depth: usize, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(depth, stmt);
+        }
+    }
+
+    fn stmt(&mut self, depth: usize, stmt: &Stmt) {
+        match (self.style, stmt) {
+            (_, Stmt::Op(instruction)) => {
+                let text = self.op(*instruction);
+                self.line(depth, &text);
+            }
+            (
+                Style::Octo,
+                Stmt::If {
+                    cond,
+                    then,
+                    otherwise,
+                },
+            ) => {
+                // leaving the loop at its end is how Octo writes while
+                if let ([Stmt::Break], []) = (&then[..], &otherwise[..]) {
+                    let text = format!("while {}", self.cond(cond.negate()));
+                    return self.line(depth, &text);
+                }
+                let cond = self.cond(*cond);
+                match (&then[..], &otherwise[..]) {
+                    ([single @ (Stmt::Op(_) | Stmt::Goto(_) | Stmt::Continue)], []) => {
+                        let text = match single {
+                            Stmt::Op(instruction) => self.op(*instruction),
+                            Stmt::Goto(target) => format!("jump {}", label(*target)),
+                            _ => "continue".to_string(),
+                        };
+                        self.line(depth, &format!("if {cond} then {text}"));
+                    }
+                    _ => {
+                        self.line(depth, &format!("if {cond} begin"));
+                        self.stmts(depth + 1, then);
+                        if !otherwise.is_empty() {
+                            self.line(depth, "else");
+                            self.stmts(depth + 1, otherwise);
+                        }
+                        self.line(depth, "end");
+                    }
+                }
+            }
+            (
+                Style::C,
+                Stmt::If {
+                    cond,
+                    then,
+                    otherwise,
+                },
+            ) => {
+                let cond = self.cond(*cond);
+                self.line(depth, &format!("if ({cond}) {{"));
+                self.stmts(depth + 1, then);
+                if !otherwise.is_empty() {
+                    self.line(depth, "} else {");
+                    self.stmts(depth + 1, otherwise);
+                }
+                self.line(depth, "}");
+            }
+            (Style::Octo, Stmt::Loop(body)) => {
+                self.line(depth, "loop");
+                self.stmts(depth + 1, body);
+                self.line(depth, "again");
+            }
+            (Style::C, Stmt::Loop(body)) => {
+                self.line(depth, "while (true) {");
+                self.stmts(depth + 1, body);
+                self.line(depth, "}");
+            }
+            (Style::Octo, Stmt::Data(opcode)) => {
+                self.line(depth, &format!("# unknown opcode 0x{opcode:04X}"))
+            }
+            (Style::C, Stmt::Data(opcode)) => {
+                self.line(depth, &format!("// unknown opcode 0x{opcode:04X}"))
+            }
+            (Style::Octo, Stmt::Break) => self.line(depth, "break"),
+            (Style::C, Stmt::Break) => self.line(depth, "break;"),
+            // Octo has no continue and again would end the loop, the
+            // pseudo-code borrows the keyword of C
+            (Style::Octo, Stmt::Continue) => self.line(depth, "continue"),
+            (Style::C, Stmt::Continue) => self.line(depth, "continue;"),
+            (Style::Octo, Stmt::Goto(target)) => {
+                self.line(depth, &format!("jump {}", label(*target)))
+            }
+            (Style::C, Stmt::Goto(target)) => {
+                self.line(depth, &format!("goto {};", label(*target)))
+            }
+            // labels aren't indented, so they stand out
+            (Style::Octo, Stmt::Label(address)) => self.line(0, &format!(": {}", label(*address))),
+            (Style::C, Stmt::Label(address)) => self.line(0, &format!("{}:", label(*address))),
+        }
+    }
+}
+
+// Writes the code reachable from the start of the rom as structured
+// pseudo-code, one function for the main program and each subroutine.
+// What can't be structured is left as gotos, and the code only reached
+// through Bnnn is missing.
+pub fn decompile(rom: &[u8], style: Style) -> String {
+    let cfg = Cfg::build(rom);
+    let names = register_names(&cfg);
+    let mut printer = Printer {
+        style,
+        names: &names,
+        out: String::new(),
+    };
+
+    let comment = match style {
+        Style::Octo => "#",
+        Style::C => "//",
+    };
+    printer.line(
+        0,
+        &format!("{comment} decompiled pseudo-code, not meant to be assembled"),
+    );
+    for (x, name) in names.iter().enumerate() {
+        if *name != format!("v{x:x}") {
+            let text = match style {
+                Style::Octo => format!(":alias {name} v{x:x}"),
+                Style::C => format!("// {name} is v{x:x}"),
+            };
+            printer.line(0, &text);
+        }
+    }
+
+    for subroutine in cfg.subroutines.values() {
+        let mut lines: Vec<Line> = subroutine
+            .blocks
+            .iter()
+            .flat_map(|start| cfg.blocks[start].lines.iter().copied())
+            .collect();
+        lines.sort_by_key(|line| line.address);
+        lines.dedup_by_key(|line| line.address);
+
+        let stmts = structure(&lines);
+        printer.line(0, "");
+        let name = sub_name(subroutine.entry);
+        match style {
+            Style::Octo => {
+                printer.line(0, &format!(": {name}"));
+                printer.stmts(1, &stmts);
+            }
+            Style::C => {
+                printer.line(0, &format!("void {name}() {{"));
+                printer.stmts(1, &stmts);
+                printer.line(0, "}");
+            }
+        }
+    }
+    printer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the output without the header and the blank line after it
+    fn lines(rom: &[u8], style: Style) -> Vec<String> {
+        decompile(rom, style)
+            .lines()
+            .skip(2)
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn if_then() {
+        let rom = [
+            0x30, 0x01, // se v0, 1
+            0x61, 0x05, // ld v1, 5
+            0x00, 0xEE, // ret
+        ];
+        assert_eq!(
+            lines(&rom, Style::Octo),
+            [": main", "    if v0 != 0x01 then v1 := 0x05", "    return"]
+        );
+        assert_eq!(
+            lines(&rom, Style::C),
+            [
+                "void main() {",
+                "    if (v0 != 0x01) {",
+                "        v1 = 0x05;",
+                "    }",
+                "    return;",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn if_else() {
+        let rom = [
+            0x30, 0x01, // 0x200: se v0, 1
+            0x12, 0x08, // 0x202: jp 0x208
+            0x61, 0x01, // 0x204: ld v1, 1
+            0x12, 0x0A, // 0x206: jp 0x20A
+            0x61, 0x02, // 0x208: ld v1, 2
+            0x00, 0xEE, // 0x20A: ret
+        ];
+        assert_eq!(
+            lines(&rom, Style::Octo),
+            [
+                ": main",
+                "    if v0 == 0x01 begin",
+                "        v1 := 0x01",
+                "    else",
+                "        v1 := 0x02",
+                "    end",
+                "    return",
+            ]
+        );
+        assert_eq!(
+            lines(&rom, Style::C),
+            [
+                "void main() {",
+                "    if (v0 == 0x01) {",
+                "        v1 = 0x01;",
+                "    } else {",
+                "        v1 = 0x02;",
+                "    }",
+                "    return;",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn while_and_continue() {
+        let rom = [
+            0x70, 0x01, // 0x200: add v0, 1
+            0x40, 0x03, // 0x202: sne v0, 3
+            0x12, 0x00, // 0x204: jp 0x200
+            0x40, 0x07, // 0x206: sne v0, 7
+            0x12, 0x0E, // 0x208: jp 0x20E
+            0x30, 0x0A, // 0x20A: se v0, 10
+            0x12, 0x00, // 0x20C: jp 0x200
+            0x00, 0xEE, // 0x20E: ret
+        ];
+        assert_eq!(
+            lines(&rom, Style::Octo),
+            [
+                ": main",
+                "    loop",
+                "        v0 += 0x01",
+                "        if v0 == 0x03 then continue",
+                "        while v0 != 0x07",
+                "        while v0 != 0x0A",
+                "    again",
+                "    return",
+            ]
+        );
+        assert_eq!(
+            lines(&rom, Style::C),
+            [
+                "void main() {",
+                "    while (true) {",
+                "        v0 += 0x01;",
+                "        if (v0 == 0x03) {",
+                "            continue;",
+                "        }",
+                "        if (v0 == 0x07) {",
+                "            break;",
+                "        }",
+                "        if (v0 == 0x0A) {",
+                "            break;",
+                "        }",
+                "    }",
+                "    return;",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn break_in_a_block() {
+        let rom = [
+            0x70, 0x01, // 0x200: add v0, 1
+            0x30, 0x05, // 0x202: se v0, 5
+            0x12, 0x0A, // 0x204: jp 0x20A
+            0x61, 0x00, // 0x206: ld v1, 0
+            0x12, 0x0E, // 0x208: jp 0x20E
+            0x71, 0x01, // 0x20A: add v1, 1
+            0x12, 0x00, // 0x20C: jp 0x200
+            0x00, 0xEE, // 0x20E: ret
+        ];
+        assert_eq!(
+            lines(&rom, Style::Octo),
+            [
+                ": main",
+                "    loop",
+                "        v0 += 0x01",
+                "        if v0 == 0x05 begin",
+                "            v1 := 0x00",
+                "            break",
+                "        end",
+                "        v1 += 0x01",
+                "    again",
+                "    return",
+            ]
+        );
+        assert_eq!(
+            lines(&rom, Style::C),
+            [
+                "void main() {",
+                "    while (true) {",
+                "        v0 += 0x01;",
+                "        if (v0 == 0x05) {",
+                "            v1 = 0x00;",
+                "            break;",
+                "        }",
+                "        v1 += 0x01;",
+                "    }",
+                "    return;",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn goto_into_a_loop() {
+        let rom = [
+            0x12, 0x04, // 0x200: jp 0x204
+            0x70, 0x01, // 0x202: add v0, 1
+            0x71, 0x02, // 0x204: add v1, 2
+            0x12, 0x02, // 0x206: jp 0x202
+        ];
+        assert_eq!(
+            lines(&rom, Style::Octo),
+            [
+                ": main",
+                "    jump label_204",
+                "    loop",
+                "        v0 += 0x01",
+                ": label_204",
+                "        v1 += 0x02",
+                "    again",
+            ]
+        );
+        assert_eq!(
+            lines(&rom, Style::C),
+            [
+                "void main() {",
+                "    goto label_204;",
+                "    while (true) {",
+                "        v0 += 0x01;",
+                "label_204:",
+                "        v1 += 0x02;",
+                "    }",
+                "}",
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_opcodes() {
+        // 5xy1 isn't an instruction
+        let rom = [0x60, 0x01, 0x51, 0x21];
+        assert_eq!(
+            lines(&rom, Style::Octo),
+            [": main", "    v0 := 0x01", "    # unknown opcode 0x5121"]
+        );
+        assert_eq!(
+            lines(&rom, Style::C),
+            [
+                "void main() {",
+                "    v0 = 0x01;",
+                "    // unknown opcode 0x5121",
+                "}",
+            ]
+        );
+    }
+}
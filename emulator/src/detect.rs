@@ -0,0 +1,409 @@
+use crate::disasm::{self, Instruction};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::{Emulator, RAM_START_ADDR};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::mem::discriminant;
+
+// instructions run on each frame of the headless run
+const RUN_TICKS_PER_FRAME: usize = 12;
+// instructions looked at after Fx55 and Fx65 for another one using I
+const LOAD_STORE_WINDOW: u16 = 8;
+
+// A guess of the platform a rom was written for, for the roms that aren't
+// in the database. The opcodes only some platforms have are the strongest
+// hint, how the rom uses I and the shifts hint at the older interpreters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    // from 0.0 (a blind guess) to 1.0 (certain)
+    pub confidence: f32,
+    // what the guess is based on, most telling first
+    pub evidence: Vec<Evidence>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evidence {
+    pub address: u16,
+    pub kind: EvidenceKind,
+    // how the opcode was found
+    pub source: Source,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    // somewhere in the rom, it may be data
+    Scan,
+    // reachable from the start by following the jumps
+    Reachable,
+    // run in the headless run
+    Executed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvidenceKind {
+    // an opcode of a platform with more instructions
+    Opcode(u16, Platform, &'static str),
+    // Fx55 writing over the code of the rom
+    SelfModifying,
+    // Fx55 or Fx65 followed by another one without setting I
+    ReliesOnIncrement,
+    // 8xy6 or 8xyE with x and y different
+    ShiftsVy,
+}
+
+impl fmt::Display for Evidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            EvidenceKind::Opcode(opcode, platform, name) => write!(
+                f,
+                "{opcode:04X} ({name}, {}) at 0x{:03X}",
+                platform.name(),
+                self.address
+            )?,
+            EvidenceKind::SelfModifying => {
+                write!(f, "writes over its own code at 0x{:03X}", self.address)?
+            }
+            EvidenceKind::ReliesOnIncrement => write!(
+                f,
+                "loads or stores again without setting I at 0x{:03X}",
+                self.address
+            )?,
+            EvidenceKind::ShiftsVy => {
+                write!(f, "shifts another register at 0x{:03X}", self.address)?
+            }
+        }
+        match self.source {
+            Source::Scan => write!(f, ", maybe data"),
+            Source::Reachable => Ok(()),
+            Source::Executed => write!(f, ", executed"),
+        }
+    }
+}
+
+// The opcodes that aren't chip-8 instructions on the COSMAC VIP, with the
+// first platform that has them
+pub fn extension_opcode(opcode: u16) -> Option<(Platform, &'static str)> {
+    let n = opcode & 0x000F;
+    let kk = opcode & 0x00FF;
+    let found = match (opcode >> 12, kk) {
+        (0, _) if opcode == 0x00FF => (Platform::SuperChip1, "high resolution"),
+        (0, _) if opcode == 0x00FE => (Platform::SuperChip1, "low resolution"),
+        (0, _) if opcode == 0x00FD => (Platform::SuperChip1, "exit"),
+        (0, _) if opcode == 0x00FB => (Platform::SuperChip, "scroll right"),
+        (0, _) if opcode == 0x00FC => (Platform::SuperChip, "scroll left"),
+        (0, _) if opcode & 0xFFF0 == 0x00C0 && n != 0 => (Platform::SuperChip, "scroll down"),
+        (0, _) if opcode & 0xFFF0 == 0x00D0 && n != 0 => (Platform::XoChip, "scroll up"),
+        // machine code routines of the VIP, 0000 is too common in data
+        (0, _) if opcode != 0 && opcode != 0x00E0 && opcode != 0x00EE => {
+            (Platform::HybridVip, "machine code call")
+        }
+        (5, _) if n == 2 => (Platform::XoChip, "save registers"),
+        (5, _) if n == 3 => (Platform::XoChip, "load registers"),
+        (0xD, _) if n == 0 => (Platform::SuperChip1, "16x16 sprite"),
+        (0xF, _) if opcode == 0xF000 => (Platform::XoChip, "long I"),
+        (0xF, _) if opcode == 0xF002 => (Platform::XoChip, "audio pattern"),
+        (0xF, 0x01) => (Platform::XoChip, "drawing plane"),
+        (0xF, 0x3A) => (Platform::XoChip, "pitch"),
+        (0xF, 0x30) => (Platform::SuperChip1, "large font"),
+        (0xF, 0x75) => (Platform::SuperChip1, "save flags"),
+        (0xF, 0x85) => (Platform::SuperChip1, "load flags"),
+        _ => return None,
+    };
+    Some(found)
+}
+
+// Guesses the platform from the bytes of the rom alone
+pub fn detect(rom: &[u8]) -> Detection {
+    let reachable = disasm::reachable(rom);
+    let mut evidence = scan(rom, &reachable);
+    evidence.extend(load_store_usage(rom, &reachable));
+    conclude(evidence)
+}
+
+// Guesses the platform from the bytes of the rom and a headless run of
+// `frames` 60hz frames without keys pressed. The run stops at the first
+// opcode this emulator doesn't have, which is already the best hint.
+pub fn detect_with_run(rom: &[u8], frames: u32) -> Detection {
+    let reachable = disasm::reachable(rom);
+    let mut evidence = scan(rom, &reachable);
+    evidence.extend(load_store_usage(rom, &reachable));
+    evidence.extend(run(rom, frames));
+    conclude(evidence)
+}
+
+fn opcode_at(rom: &[u8], address: u16) -> Option<u16> {
+    let offset = (address as usize).checked_sub(RAM_START_ADDR)?;
+    let bytes = rom.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+// the platform opcodes, every two bytes from the start
+fn scan(rom: &[u8], reachable: &BTreeSet<u16>) -> Vec<Evidence> {
+    disasm::disassemble(rom)
+        .into_iter()
+        .filter_map(|line| {
+            let (platform, name) = extension_opcode(line.opcode)?;
+            let source = if reachable.contains(&line.address) {
+                Source::Reachable
+            } else if platform == Platform::HybridVip {
+                // too common in data to say anything
+                return None;
+            } else {
+                Source::Scan
+            };
+            Some(Evidence {
+                address: line.address,
+                kind: EvidenceKind::Opcode(line.opcode, platform, name),
+                source,
+            })
+        })
+        .collect()
+}
+
+// how the reachable code uses I and the shifts
+fn load_store_usage(rom: &[u8], reachable: &BTreeSet<u16>) -> Vec<Evidence> {
+    let mut evidence = Vec::new();
+    let decode = |address| opcode_at(rom, address).and_then(Instruction::decode);
+    let found = |address, kind| Evidence {
+        address,
+        kind,
+        source: Source::Reachable,
+    };
+    // I as set by the last Annn before, in the order of the addresses
+    let mut last_i = None;
+
+    for &address in reachable {
+        let Some(instruction) = decode(address) else {
+            continue;
+        };
+        match instruction {
+            Instruction::LdI(nnn) => last_i = Some(nnn),
+            Instruction::Shr(x, y) | Instruction::Shl(x, y) if x != y => {
+                evidence.push(found(address, EvidenceKind::ShiftsVy))
+            }
+            Instruction::LdIVx(x) | Instruction::LdVxI(x) => {
+                if let (Instruction::LdIVx(_), Some(i)) = (instruction, last_i) {
+                    if reachable.range(i..=i + x as u16).next().is_some() {
+                        evidence.push(found(address, EvidenceKind::SelfModifying));
+                    }
+                }
+                // the straight line code after it, until I is set again
+                let again = (1..=LOAD_STORE_WINDOW)
+                    .map(|step| address + step * 2)
+                    .take_while(|next| reachable.contains(next))
+                    .map_while(decode)
+                    .take_while(|next| !matches!(next, Instruction::LdI(_)))
+                    .take_while(|next| {
+                        !matches!(
+                            next,
+                            Instruction::Jp(_) | Instruction::Ret | Instruction::Call(_)
+                        )
+                    })
+                    .any(|next| matches!(next, Instruction::LdIVx(_) | Instruction::LdVxI(_)));
+                if again {
+                    evidence.push(found(address, EvidenceKind::ReliesOnIncrement));
+                }
+            }
+            _ => (),
+        }
+    }
+    evidence
+}
+
+// the headless run, until an instruction the emulator can't run
+fn run(rom: &[u8], frames: u32) -> Vec<Evidence> {
+    let mut evidence = Vec::new();
+    let mut emulator = Emulator::new();
+    if emulator.load_rom(rom).is_err() {
+        return evidence;
+    }
+    // each modified address is only reported once
+    let mut modified = BTreeSet::new();
+
+    'frames: for _ in 0..frames {
+        for _ in 0..RUN_TICKS_PER_FRAME {
+            let address = emulator.pc();
+            let Some(opcode) = emulator.next_opcode() else {
+                break 'frames;
+            };
+            let changed = opcode_at(rom, address).is_some_and(|original| original != opcode);
+            if changed && modified.insert(address) {
+                evidence.push(Evidence {
+                    address,
+                    kind: EvidenceKind::SelfModifying,
+                    source: Source::Executed,
+                });
+            }
+            if let Some((platform, name)) = extension_opcode(opcode) {
+                evidence.push(Evidence {
+                    address,
+                    kind: EvidenceKind::Opcode(opcode, platform, name),
+                    source: Source::Executed,
+                });
+                break 'frames;
+            }
+            if emulator.tick().is_err() {
+                break 'frames;
+            }
+        }
+        emulator.tick_delay_timer();
+        emulator.tick_sound_timer();
+    }
+    evidence
+}
+
+fn conclude(mut evidence: Vec<Evidence>) -> Detection {
+    // the same address can be found by the scan and the run
+    evidence.sort_by(|a, b| b.source.cmp(&a.source).then(a.address.cmp(&b.address)));
+    let mut seen = HashSet::new();
+    evidence.retain(|found| seen.insert((found.address, discriminant(&found.kind))));
+
+    // the most capable platform seen in the code wins. The scan can't tell
+    // the data from the code, so it needs a few different opcodes there.
+    let opcodes = |source: Source| {
+        evidence.iter().filter_map(move |found| match found.kind {
+            EvidenceKind::Opcode(opcode, platform, _) if found.source >= source => {
+                Some((opcode, platform, found.source))
+            }
+            _ => None,
+        })
+    };
+    let in_code = opcodes(Source::Reachable)
+        .map(|(_, platform, _)| platform)
+        .max_by_key(|platform| rank(*platform));
+    let in_data: BTreeSet<_> = opcodes(Source::Scan)
+        .map(|(opcode, platform, _)| (rank(platform), opcode, platform))
+        .collect();
+    let opcode_guess = match in_code {
+        Some(platform) => {
+            let hits: Vec<_> = opcodes(Source::Reachable)
+                .filter(|(_, other, _)| rank(*other) == rank(platform))
+                .collect();
+            let confidence = if hits
+                .iter()
+                .any(|(_, _, source)| *source == Source::Executed)
+            {
+                0.95
+            } else if hits.len() > 1 {
+                0.9
+            } else {
+                0.75
+            };
+            Some((platform, confidence))
+        }
+        None if in_data.len() >= 3 => in_data.last().map(|(_, _, platform)| (*platform, 0.4)),
+        None => None,
+    };
+
+    let count = |kind: EvidenceKind| {
+        evidence
+            .iter()
+            .filter(|found| discriminant(&found.kind) == discriminant(&kind))
+            .count()
+    };
+    let self_modifying = count(EvidenceKind::SelfModifying);
+    let relies_on_increment = count(EvidenceKind::ReliesOnIncrement);
+    let shifts_vy = count(EvidenceKind::ShiftsVy);
+
+    let (platform, confidence) = match opcode_guess {
+        Some(guess) => guess,
+        None if self_modifying + relies_on_increment + shifts_vy > 0 => {
+            let hints = self_modifying + relies_on_increment + shifts_vy;
+            (Platform::OriginalChip8, if hints > 1 { 0.6 } else { 0.5 })
+        }
+        // nothing stands out, most roms run on the modern behavior
+        None => (Platform::ModernChip8, 0.3),
+    };
+
+    let mut quirks = platform.quirks();
+    if relies_on_increment > 0 {
+        quirks.memory_increment_i = true;
+    }
+    if shifts_vy > 0 && opcode_guess.is_none() {
+        quirks.shift_vy = true;
+    }
+
+    // the platform opcodes in the code first, then the hints of the quirks
+    // and the opcodes that may be data
+    evidence.sort_by_key(|found| match (found.kind, found.source) {
+        (_, Source::Scan) => 2,
+        (EvidenceKind::Opcode(..), _) => 0,
+        _ => 1,
+    });
+
+    Detection {
+        platform,
+        quirks,
+        confidence,
+        evidence,
+    }
+}
+
+// platforms by how many instructions they have
+fn rank(platform: Platform) -> u8 {
+    match platform {
+        Platform::HybridVip => 0,
+        Platform::SuperChip1 => 1,
+        Platform::SuperChip => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAX_ROM_SIZE;
+
+    // roms that used to panic the headless run
+    const FAULTY_ROMS: [(&str, &[u8]); 9] = [
+        ("return without a call", &[0x00, 0xEE]),
+        ("endless recursion", &[0x22, 0x00]),
+        ("bcd at the end of the ram", &[0xAF, 0xFF, 0xF3, 0x33]),
+        ("save at the end of the ram", &[0xAF, 0xFF, 0xF3, 0x55]),
+        ("load at the end of the ram", &[0xAF, 0xFF, 0xF3, 0x65]),
+        ("sprite at the end of the ram", &[0xAF, 0xFF, 0xD0, 0x05]),
+        ("jump past the ram", &[0x60, 0xFF, 0xBF, 0xFF]),
+        ("font of 0xFF", &[0x60, 0xFF, 0xF0, 0x29, 0x12, 0x02]),
+        ("key past the keypad", &[0x60, 0xFF, 0xE0, 0x9E, 0x12, 0x02]),
+    ];
+
+    #[test]
+    fn run_stops_at_faults() {
+        for (name, rom) in FAULTY_ROMS {
+            let detection = detect_with_run(rom, 60);
+            assert!(detection.confidence > 0.0, "{name}");
+        }
+    }
+
+    #[test]
+    fn run_survives_i_overflow() {
+        // I grows by 255 on every loop until it wraps around
+        let rom = [0x60, 0xFF, 0xF0, 0x1E, 0x12, 0x02];
+        detect_with_run(&rom, 60);
+    }
+
+    #[test]
+    fn run_skips_roms_larger_than_the_ram() {
+        let rom = vec![0; MAX_ROM_SIZE + 2];
+        let detection = detect_with_run(&rom, 10);
+        assert!(detection
+            .evidence
+            .iter()
+            .all(|evidence| evidence.source != Source::Executed));
+    }
+
+    #[test]
+    fn run_reports_executed_extension_opcodes() {
+        // high resolution, then an endless loop
+        let rom = [0x00, 0xFF, 0x12, 0x02];
+        let detection = detect_with_run(&rom, 1);
+        assert_eq!(detection.platform, Platform::SuperChip1);
+        assert!(detection
+            .evidence
+            .iter()
+            .any(|evidence| evidence.source == Source::Executed));
+    }
+}
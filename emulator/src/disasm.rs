@@ -0,0 +1,262 @@
+use crate::RAM_START_ADDR;
+use std::collections::BTreeSet;
+use std::fmt;
+
+// A decoded chip-8 instruction, named after the mnemonics of Cowgod's
+// technical reference. x and y are register numbers, nnn addresses and
+// kk bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Sys(u16),                  // 0nnn
+    Cls,                       // 00E0
+    Ret,                       // 00EE
+    Jp(u16),                   // 1nnn
+    Call(u16),                 // 2nnn
+    SeByte(u8, u8),            // 3xkk
+    SneByte(u8, u8),           // 4xkk
+    SeReg(u8, u8),             // 5xy0
+    LdByte(u8, u8),            // 6xkk
+    AddByte(u8, u8),           // 7xkk
+    LdReg(u8, u8),             // 8xy0
+    Or(u8, u8),                // 8xy1
+    And(u8, u8),               // 8xy2
+    Xor(u8, u8),               // 8xy3
+    AddReg(u8, u8),            // 8xy4
+    Sub(u8, u8),               // 8xy5
+    Shr(u8, u8),               // 8xy6
+    Subn(u8, u8),              // 8xy7
+    Shl(u8, u8),               // 8xyE
+    SneReg(u8, u8),            // 9xy0
+    LdI(u16),                  // Annn
+    JpV0(u16),                 // Bnnn
+    Rnd(u8, u8),               // Cxkk
+    Drw(u8, u8, u8),           // Dxyn
+    Skp(u8),                   // Ex9E
+    Sknp(u8),                  // ExA1
+    LdVxDt(u8),                // Fx07
+    LdVxK(u8),                 // Fx0A
+    LdDtVx(u8),                // Fx15
+    LdStVx(u8),                // Fx18
+    AddI(u8),                  // Fx1E
+    LdF(u8),                   // Fx29
+    LdB(u8),                   // Fx33
+    LdIVx(u8),                 // Fx55
+    LdVxI(u8),                 // Fx65
+}
+
+impl Instruction {
+    // None for the opcodes that aren't chip-8 instructions
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let kk = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        use Instruction::*;
+        let instruction = match (opcode >> 12, n) {
+            (0, _) => match opcode {
+                0x00E0 => Cls,
+                0x00EE => Ret,
+                _ => Sys(nnn),
+            },
+            (1, _) => Jp(nnn),
+            (2, _) => Call(nnn),
+            (3, _) => SeByte(x, kk),
+            (4, _) => SneByte(x, kk),
+            (5, 0) => SeReg(x, y),
+            (6, _) => LdByte(x, kk),
+            (7, _) => AddByte(x, kk),
+            (8, 0) => LdReg(x, y),
+            (8, 1) => Or(x, y),
+            (8, 2) => And(x, y),
+            (8, 3) => Xor(x, y),
+            (8, 4) => AddReg(x, y),
+            (8, 5) => Sub(x, y),
+            (8, 6) => Shr(x, y),
+            (8, 7) => Subn(x, y),
+            (8, 0xE) => Shl(x, y),
+            (9, 0) => SneReg(x, y),
+            (0xA, _) => LdI(nnn),
+            (0xB, _) => JpV0(nnn),
+            (0xC, _) => Rnd(x, kk),
+            (0xD, _) => Drw(x, y, n),
+            (0xE, _) => match kk {
+                0x9E => Skp(x),
+                0xA1 => Sknp(x),
+                _ => return None,
+            },
+            (0xF, _) => match kk {
+                0x07 => LdVxDt(x),
+                0x0A => LdVxK(x),
+                0x15 => LdDtVx(x),
+                0x18 => LdStVx(x),
+                0x1E => AddI(x),
+                0x29 => LdF(x),
+                0x33 => LdB(x),
+                0x55 => LdIVx(x),
+                0x65 => LdVxI(x),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    // the form of the instruction, without its operands, e.g. "LD Vx, byte"
+    pub fn mnemonic(&self) -> &'static str {
+        use Instruction::*;
+        match self {
+            Sys(_) => "SYS addr",
+            Cls => "CLS",
+            Ret => "RET",
+            Jp(_) => "JP addr",
+            Call(_) => "CALL addr",
+            SeByte(..) => "SE Vx, byte",
+            SneByte(..) => "SNE Vx, byte",
+            SeReg(..) => "SE Vx, Vy",
+            LdByte(..) => "LD Vx, byte",
+            AddByte(..) => "ADD Vx, byte",
+            LdReg(..) => "LD Vx, Vy",
+            Or(..) => "OR Vx, Vy",
+            And(..) => "AND Vx, Vy",
+            Xor(..) => "XOR Vx, Vy",
+            AddReg(..) => "ADD Vx, Vy",
+            Sub(..) => "SUB Vx, Vy",
+            Shr(..) => "SHR Vx, Vy",
+            Subn(..) => "SUBN Vx, Vy",
+            Shl(..) => "SHL Vx, Vy",
+            SneReg(..) => "SNE Vx, Vy",
+            LdI(_) => "LD I, addr",
+            JpV0(_) => "JP V0, addr",
+            Rnd(..) => "RND Vx, byte",
+            Drw(..) => "DRW Vx, Vy, nibble",
+            Skp(_) => "SKP Vx",
+            Sknp(_) => "SKNP Vx",
+            LdVxDt(_) => "LD Vx, DT",
+            LdVxK(_) => "LD Vx, K",
+            LdDtVx(_) => "LD DT, Vx",
+            LdStVx(_) => "LD ST, Vx",
+            AddI(_) => "ADD I, Vx",
+            LdF(_) => "LD F, Vx",
+            LdB(_) => "LD B, Vx",
+            LdIVx(_) => "LD [I], Vx",
+            LdVxI(_) => "LD Vx, [I]",
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+        match *self {
+            Sys(nnn) => write!(f, "SYS 0x{nnn:03X}"),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            Jp(nnn) => write!(f, "JP 0x{nnn:03X}"),
+            Call(nnn) => write!(f, "CALL 0x{nnn:03X}"),
+            SeByte(x, kk) => write!(f, "SE V{x:X}, 0x{kk:02X}"),
+            SneByte(x, kk) => write!(f, "SNE V{x:X}, 0x{kk:02X}"),
+            SeReg(x, y) => write!(f, "SE V{x:X}, V{y:X}"),
+            LdByte(x, kk) => write!(f, "LD V{x:X}, 0x{kk:02X}"),
+            AddByte(x, kk) => write!(f, "ADD V{x:X}, 0x{kk:02X}"),
+            LdReg(x, y) => write!(f, "LD V{x:X}, V{y:X}"),
+            Or(x, y) => write!(f, "OR V{x:X}, V{y:X}"),
+            And(x, y) => write!(f, "AND V{x:X}, V{y:X}"),
+            Xor(x, y) => write!(f, "XOR V{x:X}, V{y:X}"),
+            AddReg(x, y) => write!(f, "ADD V{x:X}, V{y:X}"),
+            Sub(x, y) => write!(f, "SUB V{x:X}, V{y:X}"),
+            Shr(x, y) => write!(f, "SHR V{x:X}, V{y:X}"),
+            Subn(x, y) => write!(f, "SUBN V{x:X}, V{y:X}"),
+            Shl(x, y) => write!(f, "SHL V{x:X}, V{y:X}"),
+            SneReg(x, y) => write!(f, "SNE V{x:X}, V{y:X}"),
+            LdI(nnn) => write!(f, "LD I, 0x{nnn:03X}"),
+            JpV0(nnn) => write!(f, "JP V0, 0x{nnn:03X}"),
+            Rnd(x, kk) => write!(f, "RND V{x:X}, 0x{kk:02X}"),
+            Drw(x, y, n) => write!(f, "DRW V{x:X}, V{y:X}, {n}"),
+            Skp(x) => write!(f, "SKP V{x:X}"),
+            Sknp(x) => write!(f, "SKNP V{x:X}"),
+            LdVxDt(x) => write!(f, "LD V{x:X}, DT"),
+            LdVxK(x) => write!(f, "LD V{x:X}, K"),
+            LdDtVx(x) => write!(f, "LD DT, V{x:X}"),
+            LdStVx(x) => write!(f, "LD ST, V{x:X}"),
+            AddI(x) => write!(f, "ADD I, V{x:X}"),
+            LdF(x) => write!(f, "LD F, V{x:X}"),
+            LdB(x) => write!(f, "LD B, V{x:X}"),
+            LdIVx(x) => write!(f, "LD [I], V{x:X}"),
+            LdVxI(x) => write!(f, "LD V{x:X}, [I]"),
+        }
+    }
+}
+
+// One line of a linear disassembly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub opcode: u16,
+    pub instruction: Option<Instruction>,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:03X}: {:04X}  ", self.address, self.opcode)?;
+        match self.instruction {
+            Some(instruction) => write!(f, "{instruction}"),
+            // data or an unknown opcode
+            None => write!(f, "DW 0x{:04X}", self.opcode),
+        }
+    }
+}
+
+// Decodes the rom two bytes at a time from the start, as loaded at 0x200.
+// Data mixed with the code is decoded too, so not every line is real code.
+// A trailing odd byte is returned as the high byte of a last opcode.
+pub fn disassemble(rom: &[u8]) -> Vec<Line> {
+    rom.chunks(2)
+        .enumerate()
+        .map(|(idx, bytes)| {
+            let opcode = match bytes {
+                [high, low] => u16::from_be_bytes([*high, *low]),
+                [high] => (*high as u16) << 8,
+                _ => unreachable!(),
+            };
+            Line {
+                address: (RAM_START_ADDR + idx * 2) as u16,
+                opcode,
+                instruction: Instruction::decode(opcode),
+            }
+        })
+        .collect()
+}
+
+// Addresses of the instructions reachable from the start of the rom, following
+// jumps, calls and skips. The target of Bnnn depends on V0 so it isn't
+// followed, and a path stops at an opcode that isn't a chip-8 instruction.
+pub fn reachable(rom: &[u8]) -> BTreeSet<u16> {
+    let end = RAM_START_ADDR + rom.len();
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![RAM_START_ADDR as u16];
+
+    while let Some(address) = pending.pop() {
+        let idx = address as usize;
+        // also skips the addresses outside of the rom
+        if idx < RAM_START_ADDR || idx + 1 >= end || !visited.insert(address) {
+            continue;
+        }
+        let offset = idx - RAM_START_ADDR;
+        let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+        let next = address + 2;
+
+        use Instruction::*;
+        match Instruction::decode(opcode) {
+            Some(Jp(nnn)) => pending.push(nnn),
+            Some(Call(nnn)) => pending.extend([next, nnn]),
+            Some(Ret | JpV0(_)) | None => (),
+            Some(SeByte(..) | SneByte(..) | SeReg(..) | SneReg(..) | Skp(_) | Sknp(_)) => {
+                pending.extend([next, next + 2])
+            }
+            Some(_) => pending.push(next),
+        }
+    }
+    visited
+}
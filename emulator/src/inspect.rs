@@ -0,0 +1,215 @@
+use crate::detect::{self, Detection};
+use crate::disasm::{self, Instruction, Line};
+use crate::rom;
+use std::collections::{BTreeMap, BTreeSet};
+
+// instructions looked at before a key check for the value of its register
+const KEY_LOOKBACK: u16 = 8;
+
+// What can be told about a rom without running it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub size: usize,
+    pub sha1: String,
+    pub detection: Detection,
+    // reachable instructions by mnemonic, the most used first
+    pub histogram: Vec<(&'static str, usize)>,
+    // reachable opcodes the emulator can't run
+    pub invalid: Vec<Line>,
+    // bytes of the rom reachable as instructions
+    pub reachable_bytes: usize,
+    pub keys: KeyUsage,
+}
+
+impl Report {
+    // from 0.0 to 1.0, the rest is data or code only reached through Bnnn
+    pub fn reachable_ratio(&self) -> f32 {
+        if self.size == 0 {
+            return 0.0;
+        }
+        self.reachable_bytes as f32 / self.size as f32
+    }
+}
+
+// The keys the rom reads, from the key instructions of the reachable code
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyUsage {
+    // keys checked by Ex9E and ExA1 with a register loaded just before
+    pub checked: BTreeSet<u8>,
+    // Ex9E and ExA1 with a register computed some other way
+    pub computed_checks: usize,
+    // Fx0A, waiting for any key
+    pub waits: usize,
+}
+
+pub fn inspect(rom: &[u8]) -> Report {
+    let reachable = disasm::reachable(rom);
+    let lines: BTreeMap<u16, Line> = disasm::disassemble(rom)
+        .into_iter()
+        .filter(|line| reachable.contains(&line.address))
+        .map(|line| (line.address, line))
+        .collect();
+
+    let mut counts: BTreeMap<&'static str, usize> = BTreeMap::new();
+    let mut invalid = Vec::new();
+    let mut keys = KeyUsage::default();
+    for line in lines.values() {
+        let Some(instruction) = line.instruction else {
+            invalid.push(*line);
+            continue;
+        };
+        *counts.entry(instruction.mnemonic()).or_default() += 1;
+        match instruction {
+            // 0000 is run as a no-op, the machine code routines aren't
+            Instruction::Sys(nnn) if nnn != 0 => invalid.push(*line),
+            Instruction::Skp(x) | Instruction::Sknp(x) => {
+                match loaded_value(&lines, line.address, x) {
+                    Some(key) => {
+                        keys.checked.insert(key & 0xF);
+                    }
+                    None => keys.computed_checks += 1,
+                }
+            }
+            Instruction::LdVxK(_) => keys.waits += 1,
+            _ => (),
+        }
+    }
+
+    let mut histogram: Vec<_> = counts.into_iter().collect();
+    histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    Report {
+        size: rom.len(),
+        sha1: rom::sha1_hex(rom),
+        detection: detect::detect(rom),
+        histogram,
+        invalid,
+        reachable_bytes: lines.len() * 2,
+        keys,
+    }
+}
+
+// the byte loaded into Vx by the straight line code before the address,
+// None if Vx is set some other way
+fn loaded_value(lines: &BTreeMap<u16, Line>, address: u16, x: u8) -> Option<u8> {
+    let mut address = address;
+    for _ in 0..KEY_LOOKBACK {
+        address = address.checked_sub(2)?;
+        match lines.get(&address)?.instruction? {
+            Instruction::LdByte(reg, kk) if reg == x => return Some(kk),
+            Instruction::Jp(_) | Instruction::Ret | Instruction::JpV0(_) => return None,
+            instruction if writes(instruction, x) => return None,
+            _ => (),
+        }
+    }
+    None
+}
+
+// true if the instruction changes Vx
+fn writes(instruction: Instruction, x: u8) -> bool {
+    use Instruction::*;
+    match instruction {
+        LdByte(reg, _) | AddByte(reg, _) | Rnd(reg, _) => reg == x,
+        LdReg(reg, _) => reg == x,
+        // VF is also set by the logic instructions with the vf_reset quirk
+        Or(reg, _)
+        | And(reg, _)
+        | Xor(reg, _)
+        | AddReg(reg, _)
+        | Sub(reg, _)
+        | Shr(reg, _)
+        | Subn(reg, _)
+        | Shl(reg, _) => reg == x || x == 0xF,
+        LdVxDt(reg) | LdVxK(reg) => reg == x,
+        LdVxI(last) => x <= last,
+        Drw(..) => x == 0xF,
+        // the called code can change any register
+        Call(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(rom: &[u8]) -> KeyUsage {
+        inspect(rom).keys
+    }
+
+    #[test]
+    fn checked_keys() {
+        let rom = [
+            0x60, 0x05, // ld v0, 5
+            0xE0, 0x9E, // skp v0
+            0x61, 0x1A, // ld v1, 0x1A
+            0xE1, 0xA1, // sknp v1, the key is the low nibble
+            0x12, 0x00, // jp 0x200
+        ];
+        let expected = KeyUsage {
+            checked: BTreeSet::from([0x5, 0xA]),
+            ..KeyUsage::default()
+        };
+        assert_eq!(keys(&rom), expected);
+    }
+
+    #[test]
+    fn keys_loaded_too_far_back_are_computed() {
+        // ld v0, 5 then unrelated loads of v1, and the check
+        let rom = |loads: usize| {
+            let mut rom = vec![0x60, 0x05];
+            for _ in 0..loads {
+                rom.extend([0x61, 0x00]);
+            }
+            rom.extend([0xE0, 0x9E, 0x12, 0x00]);
+            rom
+        };
+        assert_eq!(keys(&rom(7)).checked, BTreeSet::from([5]));
+        assert_eq!(keys(&rom(8)).computed_checks, 1);
+    }
+
+    #[test]
+    fn computed_keys() {
+        let roms: [(&str, &[u8]); 5] = [
+            ("added", &[0x60, 0x05, 0x70, 0x01, 0xE0, 0x9E, 0x12, 0x00]),
+            ("random", &[0xC0, 0x0F, 0xE0, 0x9E, 0x12, 0x00]),
+            // the called code can change it
+            (
+                "call",
+                &[0x60, 0x05, 0x22, 0x08, 0xE0, 0x9E, 0x12, 0x00, 0x00, 0xEE],
+            ),
+            // the add sets VF
+            ("vf", &[0x6F, 0x05, 0x80, 0x14, 0xEF, 0x9E, 0x12, 0x00]),
+            // the check is reached by the jump, not after the load
+            ("jump", &[0x60, 0x05, 0x12, 0x04, 0xE0, 0x9E, 0x12, 0x00]),
+        ];
+        for (name, rom) in roms {
+            let keys = keys(rom);
+            assert_eq!(keys.computed_checks, 1, "{name}");
+            assert!(keys.checked.is_empty(), "{name}");
+        }
+    }
+
+    #[test]
+    fn key_waits() {
+        let rom = [0xF3, 0x0A, 0xF4, 0x0A, 0x12, 0x00];
+        let keys = keys(&rom);
+        assert_eq!(keys.waits, 2);
+        assert!(keys.checked.is_empty());
+        assert_eq!(keys.computed_checks, 0);
+    }
+
+    #[test]
+    fn sys_and_unknown_opcodes_are_invalid() {
+        let rom = [
+            0x00, 0x00, // 0x200: sys 0, run as a no-op
+            0x01, 0x23, // 0x202: sys 0x123
+            0x51, 0x21, // 0x204: not an instruction
+        ];
+        let report = inspect(&rom);
+        let invalid: Vec<u16> = report.invalid.iter().map(|line| line.address).collect();
+        assert_eq!(invalid, [0x202, 0x204]);
+        assert_eq!(report.reachable_bytes, 6);
+        assert_eq!(report.reachable_ratio(), 1.0);
+    }
+}
@@ -0,0 +1,254 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+// Physical keys are named after the KeyboardEvent.code values of the web
+// ("KeyQ", "Digit1", "ArrowUp", ...), so the layout follows the position of
+// the keys and not what is printed on them (AZERTY, Dvorak, ...).
+// The default maps the COSMAC VIP hex keypad to the left side of the keyboard:
+//   1 2 3 C      1 2 3 4
+//   4 5 6 D  ->  Q W E R
+//   7 8 9 E      A S D F
+//   A 0 B F      Z X C V
+pub const DEFAULT_BINDINGS: [(u8, &str); 16] = [
+    (0x1, "Digit1"),
+    (0x2, "Digit2"),
+    (0x3, "Digit3"),
+    (0xC, "Digit4"),
+    (0x4, "KeyQ"),
+    (0x5, "KeyW"),
+    (0x6, "KeyE"),
+    (0xD, "KeyR"),
+    (0x7, "KeyA"),
+    (0x8, "KeyS"),
+    (0x9, "KeyD"),
+    (0xE, "KeyF"),
+    (0xA, "KeyZ"),
+    (0x0, "KeyX"),
+    (0xB, "KeyC"),
+    (0xF, "KeyV"),
+];
+
+// Gamepad inputs share the name space of the keys, using the buttons of the
+// standard layout: GamepadA, GamepadB, GamepadX, GamepadY, GamepadLeftShoulder,
+// GamepadRightShoulder, GamepadLeftTrigger, GamepadRightTrigger,
+// GamepadDPad{Up,Down,Left,Right}, GamepadLeftStick{Up,Down,Left,Right} and
+// GamepadRightStick{Up,Down,Left,Right}. The default follows the Octo
+// convention used by most modern games: 5/7/8/9 to move and 6 for action.
+pub const DEFAULT_GAMEPAD_BINDINGS: [(u8, &str); 10] = [
+    (0x5, "GamepadDPadUp"),
+    (0x8, "GamepadDPadDown"),
+    (0x7, "GamepadDPadLeft"),
+    (0x9, "GamepadDPadRight"),
+    (0x5, "GamepadLeftStickUp"),
+    (0x8, "GamepadLeftStickDown"),
+    (0x7, "GamepadLeftStickLeft"),
+    (0x9, "GamepadLeftStickRight"),
+    (0x6, "GamepadA"),
+    (0x4, "GamepadB"),
+];
+
+// Actions on the reserved buttons, they can't be bound to chip-8 keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    Pause,     // Start
+    Reset,     // Back
+    SaveState, // left stick click
+    LoadState, // right stick click
+}
+
+impl Hotkey {
+    // the hotkey of a reserved gamepad button, named like the other inputs
+    pub fn from_button(name: &str) -> Option<Hotkey> {
+        match name {
+            "GamepadStart" => Some(Hotkey::Pause),
+            "GamepadBack" => Some(Hotkey::Reset),
+            "GamepadLeftStick" => Some(Hotkey::SaveState),
+            "GamepadRightStick" => Some(Hotkey::LoadState),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hotkey::Pause => "pause",
+            Hotkey::Reset => "reset",
+            Hotkey::SaveState => "save_state",
+            Hotkey::LoadState => "load_state",
+        }
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pause" => Ok(Hotkey::Pause),
+            "reset" => Ok(Hotkey::Reset),
+            "save_state" => Ok(Hotkey::SaveState),
+            "load_state" => Ok(Hotkey::LoadState),
+            _ => Err(format!("Unknown hotkey '{s}'")),
+        }
+    }
+}
+
+// A change of a gamepad, as reported by the frontends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadInput {
+    // an input named like in the keymap, e.g "GamepadA", and if it is pressed
+    Key(&'static str, bool),
+    Hotkey(Hotkey),
+}
+
+// Maps physical keys to chip-8 keys, a chip-8 key can have many physical keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: HashMap<String, u8>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = DEFAULT_BINDINGS
+            .iter()
+            .chain(DEFAULT_GAMEPAD_BINDINGS.iter())
+            .map(|(hex, code)| (code.to_string(), *hex))
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    // a keymap without any binding
+    pub fn empty() -> Self {
+        Self {
+            bindings: HashMap::new(),
+        }
+    }
+
+    // chip-8 key bound to the physical key
+    pub fn key(&self, code: &str) -> Option<u8> {
+        self.bindings.get(code).copied()
+    }
+
+    pub fn bind(&mut self, code: &str, hex: u8) {
+        self.bindings.insert(code.to_string(), hex & 0xF);
+    }
+
+    // replaces every physical key of the chip-8 key
+    pub fn set_bindings(&mut self, hex: u8, codes: &[String]) {
+        self.bindings.retain(|_, bound| *bound != hex);
+        for code in codes {
+            self.bind(code, hex);
+        }
+    }
+
+    // physical keys bound to the chip-8 key, sorted
+    pub fn codes_for(&self, hex: u8) -> Vec<&str> {
+        let mut codes: Vec<_> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == hex)
+            .map(|(code, _)| code.as_str())
+            .collect();
+        codes.sort_unstable();
+        codes
+    }
+}
+
+// either a single key or a list of keys
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyList {
+    pub fn to_vec(&self) -> Vec<String> {
+        match self {
+            KeyList::One(code) => vec![code.clone()],
+            KeyList::Many(codes) => codes.clone(),
+        }
+    }
+}
+
+// chip-8 key ("0" to "F") -> physical keys
+pub type KeyBindings = BTreeMap<String, KeyList>;
+
+// Keymap as written in the configuration files (TOML on the desktop, JSON on
+// the web). Only the chip-8 keys listed are changed from the default layout,
+// and the roms section overrides them again for a rom, keyed by its SHA-1:
+//
+//   [keys]
+//   5 = ["KeyW", "ArrowUp"]
+//
+//   [roms.0123456789abcdef0123456789abcdef01234567]
+//   4 = "ArrowLeft"
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeymapConfig {
+    #[serde(default)]
+    pub keys: KeyBindings,
+    #[serde(default, deserialize_with = "lowercase_keys")]
+    pub roms: BTreeMap<String, KeyBindings>,
+}
+
+// the SHA-1 of the roms are looked up in lowercase
+fn lowercase_keys<'de, D>(deserializer: D) -> Result<BTreeMap<String, KeyBindings>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let roms = BTreeMap::<String, KeyBindings>::deserialize(deserializer)?;
+    Ok(roms
+        .into_iter()
+        .map(|(sha1, bindings)| (sha1.to_ascii_lowercase(), bindings))
+        .collect())
+}
+
+impl KeymapConfig {
+    // keymap for the rom with the given SHA-1, or the general one for None
+    pub fn keymap_for(&self, rom_sha1: Option<&str>) -> Result<Keymap, String> {
+        let mut keymap = Keymap::default();
+        apply_bindings(&mut keymap, &self.keys)?;
+
+        let rom_bindings = rom_sha1.and_then(|sha1| self.roms.get(&sha1.to_ascii_lowercase()));
+        if let Some(bindings) = rom_bindings {
+            apply_bindings(&mut keymap, bindings)?;
+        }
+        Ok(keymap)
+    }
+}
+
+pub fn apply_bindings(keymap: &mut Keymap, bindings: &KeyBindings) -> Result<(), String> {
+    for (key, codes) in bindings {
+        let hex = parse_hex_key(key)?;
+        keymap.set_bindings(hex, &codes.to_vec());
+    }
+    Ok(())
+}
+
+// "0" to "F", optionally prefixed with "0x"
+pub fn parse_hex_key(key: &str) -> Result<u8, String> {
+    let digits = key.trim_start_matches("0x").trim_start_matches("0X");
+    match u8::from_str_radix(digits, 16) {
+        Ok(hex) if digits.len() == 1 => Ok(hex),
+        _ => Err(format!("Invalid chip-8 key '{key}', expected 0 to F")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_sections_match_any_case() {
+        let json = r#"{"roms": {"ABCDEF0123": {"4": "ArrowLeft"}}}"#;
+        let config: KeymapConfig = serde_json::from_str(json).unwrap();
+        for sha1 in ["abcdef0123", "ABCDEF0123", "AbCdEf0123"] {
+            let keymap = config.keymap_for(Some(sha1)).unwrap();
+            assert_eq!(keymap.key("ArrowLeft"), Some(4), "{sha1}");
+        }
+        let general = config.keymap_for(None).unwrap();
+        assert_eq!(general.key("ArrowLeft"), None);
+    }
+}
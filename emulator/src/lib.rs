@@ -1,3 +1,20 @@
+pub mod audio;
+pub mod cfg;
+pub mod database;
+pub mod decompile;
+pub mod detect;
+pub mod disasm;
+pub mod inspect;
+pub mod keymap;
+pub mod octo;
+pub mod palette;
+pub mod phosphor;
+pub mod platform;
+pub mod quirks;
+pub mod render;
+pub mod rom;
+pub mod wav;
+
 pub const DISPLAY_WIDTH: usize = 64; // 64 pixels
 pub const DISPLAY_HEIGHT: usize = 32; // 32 pixels
 const DISPLAY_SIZE: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT; // 2048 pixels
@@ -7,6 +24,11 @@ const REGISTER_SIZE: usize = 16;
 const STACK_SIZE: usize = 16;
 const KEYS_SIZE: usize = 16;
 const RAM_START_ADDR: usize = 0x200;
+// the rom is loaded after the interpreter area, up to the end of the ram
+pub const MAX_ROM_SIZE: usize = RAM_SIZE - RAM_START_ADDR;
+
+use quirks::Quirks;
+use std::fmt;
 
 const FONTSET_SIZE: usize = 80;
 const FONTSET: [u8; FONTSET_SIZE] = [
@@ -28,6 +50,61 @@ const FONTSET: [u8; FONTSET_SIZE] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+// 4x5 sprite of a hex digit from the built-in font, one byte per row,
+// the pixels are the 4 high bits
+pub fn font_sprite(digit: u8) -> &'static [u8] {
+    let start = (digit & 0xF) as usize * 5;
+    &FONTSET[start..start + 5]
+}
+
+// progress of a FX0A instruction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyWait {
+    #[default]
+    Idle,
+    // waiting for any key to be pressed
+    Press,
+    // the key was pressed, waiting for it to be released
+    Release(u8),
+}
+
+// Why an instruction couldn't run, `pc` is its address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    // an opcode of a later platform, or data run as code
+    UnknownOpcode { pc: u16, opcode: u16 },
+    // a call with all the 16 levels of the stack used
+    StackOverflow { pc: u16 },
+    // a return without a call
+    StackUnderflow { pc: u16 },
+    // a read or write up to `address`, which is past the end of the ram
+    OutOfMemory { pc: u16, address: u16 },
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Fault::UnknownOpcode { pc, opcode } => {
+                write!(f, "Unknown opcode 0x{opcode:04X} at 0x{pc:03X}")
+            }
+            Fault::StackOverflow { pc } => write!(
+                f,
+                "Stack overflow at 0x{pc:03X}, more than {STACK_SIZE} nested calls"
+            ),
+            Fault::StackUnderflow { pc } => {
+                write!(f, "Stack underflow at 0x{pc:03X}, a return without a call")
+            }
+            Fault::OutOfMemory { pc, address } => write!(
+                f,
+                "Memory access past the end of the ram at 0x{pc:03X}, up to 0x{address:03X}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Fault {}
+
+#[derive(Clone)]
 pub struct Emulator {
     ram: [u8; RAM_SIZE],
     v_reg: [u8; REGISTER_SIZE],
@@ -38,11 +115,19 @@ pub struct Emulator {
     sp: u8,  // stack pointer
     stack: [u16; STACK_SIZE],
     keys: [bool; KEYS_SIZE], // array for storing keyboard input
+    key_wait: KeyWait,
+    quirks: Quirks,
     // array for storing pixels stater
     // because a pixel is either on or off, using bool is fine
     pub display: [bool; DISPLAY_SIZE],
 }
 
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Emulator {
     pub fn new() -> Self {
         let mut emu = Self {
@@ -55,6 +140,8 @@ impl Emulator {
             pc: RAM_START_ADDR as u16,
             stack: [0; STACK_SIZE],
             keys: [false; KEYS_SIZE],
+            key_wait: KeyWait::Idle,
+            quirks: Quirks::default(),
             display: [false; DISPLAY_SIZE],
         };
         // load the font into the ram
@@ -62,10 +149,18 @@ impl Emulator {
         emu
     }
 
-    pub fn load_rom(&mut self, rom: &[u8]) {
+    // fails when the rom doesn't fit in the ram after the interpreter area
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), String> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(format!(
+                "The rom has {} bytes, the maximum is {MAX_ROM_SIZE}",
+                rom.len()
+            ));
+        }
         let start = RAM_START_ADDR;
         let end = RAM_START_ADDR + rom.len();
         self.ram[start..end].copy_from_slice(rom);
+        Ok(())
     }
 
     pub fn reset(&mut self) {
@@ -77,6 +172,7 @@ impl Emulator {
         self.sp = 0;
         self.stack = [0; STACK_SIZE];
         self.keys = [false; KEYS_SIZE];
+        self.key_wait = KeyWait::Idle;
         self.dt = 0;
         self.st = 0;
         self.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
@@ -86,6 +182,38 @@ impl Emulator {
         self.keys[code as usize] = pressed
     }
 
+    pub fn is_key_pressed(&self, code: u8) -> bool {
+        self.keys[code as usize]
+    }
+
+    // true while a FX0A instruction is blocking the program
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait != KeyWait::Idle
+    }
+
+    pub fn key_wait(&self) -> KeyWait {
+        self.key_wait
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    // opcode the next tick runs, None when the pc is past the end of the ram
+    pub fn next_opcode(&self) -> Option<u16> {
+        let pc = self.pc as usize;
+        let bytes = self.ram.get(pc..pc + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
     // push the value into the stack
     fn push(&mut self, value: u16) {
         self.stack[self.sp as usize] = value;
@@ -105,6 +233,11 @@ impl Emulator {
         }
     }
 
+    // true while the sound timer is active, i.e, the buzzer should be on
+    pub fn is_sound_playing(&self) -> bool {
+        self.st > 0
+    }
+
     pub fn tick_sound_timer(&mut self) -> bool {
         if self.st > 0 {
             if self.st == 1 {
@@ -116,15 +249,23 @@ impl Emulator {
         false
     }
 
-    pub fn tick(&mut self) {
-        let instruction = self.get_next_instruction();
+    // runs one instruction, an instruction that faults isn't run and stays
+    // the next one
+    pub fn tick(&mut self) -> Result<(), Fault> {
+        let pc = self.pc;
+        let instruction = self.next_opcode().ok_or(Fault::OutOfMemory {
+            pc,
+            address: pc.saturating_add(1),
+        })?;
+        self.check(pc, instruction)?;
+        self.pc += 2;
 
         // decode the instruction
         let decoded_instruction: [u16; 4] = [
             ((instruction & 0xF000) >> 12), // first 4 bits
             ((instruction & 0x0F00) >> 8),
             ((instruction & 0x00F0) >> 4),
-            ((instruction & 0x000F) >> 0), // last 4 bits
+            (instruction & 0x000F), // last 4 bits
         ];
 
         match decoded_instruction {
@@ -187,14 +328,23 @@ impl Emulator {
             // OR Vx, Vy -> Set Vx = Vx OR Vy
             [8, x, y, 1] => {
                 self.v_reg[x as usize] |= self.v_reg[y as usize];
+                if self.quirks.vf_reset {
+                    self.v_reg[0xF] = 0;
+                }
             }
             // AND Vx, Vy -> Set Vx = Vx AND Vy
             [8, x, y, 2] => {
                 self.v_reg[x as usize] &= self.v_reg[y as usize];
+                if self.quirks.vf_reset {
+                    self.v_reg[0xF] = 0;
+                }
             }
             // XOR Vx, Vy -> Set Vx = Vx XOR Vy
             [8, x, y, 3] => {
                 self.v_reg[x as usize] ^= self.v_reg[y as usize];
+                if self.quirks.vf_reset {
+                    self.v_reg[0xF] = 0;
+                }
             }
             // ADD Vx, Vy -> Set Vx = Vx + Vy, Set VF = carry
             [8, x, y, 4] => {
@@ -211,8 +361,11 @@ impl Emulator {
                 self.v_reg[0xF] = (!borrow) as u8;
             }
             // SHR, Vx {, Vy} -> Set Vx = Vx SHR 1;
-            [8, x, _, 6] => {
+            [8, x, y, 6] => {
                 let x = x as usize;
+                if self.quirks.shift_vy {
+                    self.v_reg[x] = self.v_reg[y as usize];
+                }
                 let lsb = self.v_reg[x] & 1; // least significant bit of Vx
                 self.v_reg[x] >>= 1; // divide Vx by 2 (right bit shift)
                 self.v_reg[0xF] = lsb;
@@ -225,8 +378,11 @@ impl Emulator {
                 self.v_reg[0xF] = (!borrow) as u8;
             }
             // SHL, Vx {, Vy} -> Set Vx = Vx SHL 1
-            [8, x, _, 0xE] => {
+            [8, x, y, 0xE] => {
                 let x = x as usize;
+                if self.quirks.shift_vy {
+                    self.v_reg[x] = self.v_reg[y as usize];
+                }
                 let msb = (self.v_reg[x] >> 7) & 1; // most significant bit of Vx
                 self.v_reg[x] <<= 1; // multiply Vx by 2 (left bit shift)
                 self.v_reg[0xF] = msb;
@@ -243,9 +399,10 @@ impl Emulator {
                 self.i_reg = nnn;
             }
             // JP, V0, addr -> Jump to location nnn + V0
-            [0xB, ..] => {
+            [0xB, x, ..] => {
                 let nnn = instruction & 0xFFF;
-                self.pc = nnn + self.v_reg[0] as u16;
+                let offset = if self.quirks.jump_vx { x } else { 0 };
+                self.pc = nnn + self.v_reg[offset as usize] as u16;
             }
             // RND Vx, byte -> Set Vx = random_byte AND kk;
             [0xC, x, ..] => {
@@ -256,20 +413,27 @@ impl Emulator {
             // at memory location I at (Vx, Vy), SET VF = collision
             [0xD, x, y, n] => {
                 let (x, y) = (x as usize, y as usize);
-                let vx = self.v_reg[x];
-                let vy = self.v_reg[y];
+                let (d_width, d_height) = (DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16);
+                // the position always wraps, only the pixels past the edges are clipped
+                let vx = self.v_reg[x] as u16 % d_width;
+                let vy = self.v_reg[y] as u16 % d_height;
                 let i = self.i_reg as usize;
 
                 self.v_reg[0xF] = 0;
 
                 for y in 0..n {
+                    if self.quirks.clip_sprites && vy + y >= d_height {
+                        break;
+                    }
                     let pixel = self.ram[i + y as usize];
                     for x in 0..8 {
+                        if self.quirks.clip_sprites && vx + x >= d_width {
+                            break;
+                        }
                         let msb = 0x80; // most significant bit of the pixel
                         if (pixel & (msb >> x)) != 0 {
-                            let (d_width, d_height) = (DISPLAY_WIDTH as u16, DISPLAY_HEIGHT as u16);
-                            let wrapped_x = (vx as u16 + x) % d_width;
-                            let wrapped_y = (vy as u16 + y) % d_height;
+                            let wrapped_x = (vx + x) % d_width;
+                            let wrapped_y = (vy + y) % d_height;
                             // index of the pixel
                             let idx = (wrapped_x + wrapped_y * d_width) as usize;
 
@@ -284,13 +448,13 @@ impl Emulator {
             }
             // SKP Vx -> Skip next instruction if key with value of Vx is pressed
             [0xE, x, 9, 0xE] => {
-                if self.keys[self.v_reg[x as usize] as usize] {
+                if self.keys[(self.v_reg[x as usize] & 0xF) as usize] {
                     self.pc += 2;
                 }
             }
             // SKP Vx -> Skip next instruction if key with value of Vx is not pressed
             [0xE, x, 0xA, 1] => {
-                if !self.keys[self.v_reg[x as usize] as usize] {
+                if !self.keys[(self.v_reg[x as usize] & 0xF) as usize] {
                     self.pc += 2;
                 }
             }
@@ -298,19 +462,28 @@ impl Emulator {
             [0xF, x, 0, 7] => {
                 self.v_reg[x as usize] = self.dt;
             }
-            // LD, Vx, K -> Wait for a key press, store the value of the key in Vx
+            // LD, Vx, K -> Wait for a key press and release, store the value of the key in Vx
             [0xF, x, 0, 0xA] => {
-                let mut pressed = false;
-                for (idx, key) in self.keys.into_iter().enumerate() {
-                    if key {
-                        self.v_reg[x as usize] = idx as u8;
-                        pressed = true;
-                        break;
+                let pressed_key = self.keys.iter().position(|&key| key).map(|key| key as u8);
+
+                self.key_wait = match self.key_wait {
+                    KeyWait::Idle | KeyWait::Press => match pressed_key {
+                        Some(key) if self.quirks.key_wait_on_press => {
+                            self.v_reg[x as usize] = key;
+                            KeyWait::Idle
+                        }
+                        Some(key) => KeyWait::Release(key),
+                        None => KeyWait::Press,
+                    },
+                    KeyWait::Release(key) if !self.keys[key as usize] => {
+                        self.v_reg[x as usize] = key;
+                        KeyWait::Idle
                     }
-                }
+                    wait => wait,
+                };
 
-                // Continue wating for the key press
-                if !pressed {
+                // Continue wating for the key
+                if self.key_wait != KeyWait::Idle {
                     self.pc -= 2;
                 }
             }
@@ -324,11 +497,12 @@ impl Emulator {
             }
             // ADD I, Vx -> Set i_reg = i_reg + Vx
             [0xF, x, 1, 0xE] => {
-                self.i_reg += self.v_reg[x as usize] as u16;
+                self.i_reg = self.i_reg.wrapping_add(self.v_reg[x as usize] as u16);
             }
             // LD F, Vx -> Set i_reg = location of sprite for digit Vx
             [0xF, x, 2, 9] => {
-                self.i_reg = (self.v_reg[x as usize] * 0x5) as u16;
+                // only the low digit has a sprite
+                self.i_reg = (self.v_reg[x as usize] & 0xF) as u16 * 5;
             }
             // LD B, Vx -> Store BCD representation of Vx
             // in memory locations  I, I + 1, and I + 2
@@ -347,6 +521,9 @@ impl Emulator {
                 for idx in 0..=x {
                     self.ram[i + idx] = self.v_reg[idx];
                 }
+                if self.quirks.memory_increment_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
+                }
             }
             // LD, Vx, [I] -> Read registers V0 through
             // VX from memory starting at location I
@@ -356,20 +533,147 @@ impl Emulator {
                 for idx in 0..=x {
                     self.v_reg[idx] = self.ram[i + idx];
                 }
+                if self.quirks.memory_increment_i {
+                    self.i_reg = self.i_reg.wrapping_add(x as u16 + 1);
+                }
             }
             _ => {
-                dbg!(decoded_instruction);
-                unreachable!("Error. Unknown instruction: 0x{:x}", instruction)
+                self.pc = pc;
+                return Err(Fault::UnknownOpcode {
+                    pc,
+                    opcode: instruction,
+                });
             }
         }
+        Ok(())
     }
 
-    fn get_next_instruction(&mut self) -> u16 {
-        let pc = self.pc as usize;
-        let higher_byte = self.ram[pc] as u16;
-        let lower_byte = self.ram[pc + 1] as u16;
-        let instruction: u16 = (higher_byte << 8) | lower_byte;
-        self.pc += 2;
-        instruction
+    // the stack and memory faults of the instruction at pc, checked before
+    // running it so a faulting instruction changes nothing
+    fn check(&self, pc: u16, instruction: u16) -> Result<(), Fault> {
+        let x = ((instruction & 0x0F00) >> 8) as usize;
+        // bytes read or written from I
+        let memory = match (instruction >> 12, instruction & 0x00FF) {
+            (0, 0xEE) if instruction == 0x00EE && self.sp == 0 => {
+                return Err(Fault::StackUnderflow { pc })
+            }
+            (2, _) if self.sp as usize == STACK_SIZE => return Err(Fault::StackOverflow { pc }),
+            (0xD, _) => (instruction & 0x000F) as usize,
+            (0xF, 0x33) => 3,
+            (0xF, 0x55 | 0x65) => x + 1,
+            _ => 0,
+        };
+        let end = self.i_reg as usize + memory;
+        if memory > 0 && end > RAM_SIZE {
+            return Err(Fault::OutOfMemory {
+                pc,
+                address: (end - 1) as u16,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_rom_fills_the_ram_up_to_the_end() {
+        let mut emulator = Emulator::new();
+        let rom = vec![0xAB; MAX_ROM_SIZE];
+        assert_eq!(emulator.load_rom(&rom), Ok(()));
+        assert_eq!(emulator.ram[RAM_START_ADDR..], rom[..]);
+
+        let error = emulator.load_rom(&[0; MAX_ROM_SIZE + 1]).unwrap_err();
+        assert_eq!(error, "The rom has 3585 bytes, the maximum is 3584");
+    }
+
+    // LD V3, K, then an endless loop
+    fn key_wait_emulator(quirks: Quirks) -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator.set_quirks(quirks);
+        emulator.load_rom(&[0xF3, 0x0A, 0x12, 0x02]).unwrap();
+        emulator
+    }
+
+    #[test]
+    fn key_wait_stores_the_key_on_release() {
+        let mut emulator = key_wait_emulator(Quirks::default());
+        emulator.tick().unwrap();
+        assert_eq!(emulator.key_wait(), KeyWait::Press);
+        assert_eq!(emulator.pc, 0x200);
+
+        emulator.set_key_press(0x7, true);
+        emulator.tick().unwrap();
+        assert_eq!(emulator.key_wait(), KeyWait::Release(0x7));
+        assert_eq!(emulator.pc, 0x200);
+
+        emulator.set_key_press(0x7, false);
+        emulator.tick().unwrap();
+        assert_eq!(emulator.key_wait(), KeyWait::Idle);
+        assert_eq!(emulator.v_reg[3], 0x7);
+        assert_eq!(emulator.pc, 0x202);
+    }
+
+    #[test]
+    fn key_wait_keeps_waiting_while_the_key_is_held() {
+        let mut emulator = key_wait_emulator(Quirks::default());
+        emulator.tick().unwrap();
+        emulator.set_key_press(0x7, true);
+        for _ in 0..10 {
+            emulator.tick().unwrap();
+            assert_eq!(emulator.key_wait(), KeyWait::Release(0x7));
+            assert_eq!(emulator.pc, 0x200);
+        }
+        // other keys don't complete it
+        emulator.set_key_press(0x2, true);
+        emulator.tick().unwrap();
+        assert_eq!(emulator.pc, 0x200);
+        assert!(emulator.is_waiting_for_key());
+    }
+
+    #[test]
+    fn key_wait_on_press_stores_the_key_on_press() {
+        let quirks = Quirks {
+            key_wait_on_press: true,
+            ..Quirks::default()
+        };
+        let mut emulator = key_wait_emulator(quirks);
+        emulator.tick().unwrap();
+        assert_eq!(emulator.pc, 0x200);
+
+        emulator.set_key_press(0xA, true);
+        emulator.tick().unwrap();
+        assert_eq!(emulator.key_wait(), KeyWait::Idle);
+        assert_eq!(emulator.v_reg[3], 0xA);
+        assert_eq!(emulator.pc, 0x202);
+    }
+
+    #[test]
+    fn key_wait_with_a_key_held_at_entry() {
+        let mut emulator = key_wait_emulator(Quirks::default());
+        emulator.set_key_press(0x5, true);
+        emulator.tick().unwrap();
+        assert_eq!(emulator.key_wait(), KeyWait::Release(0x5));
+        assert_eq!(emulator.pc, 0x200);
+
+        emulator.set_key_press(0x5, false);
+        emulator.tick().unwrap();
+        assert_eq!(emulator.v_reg[3], 0x5);
+        assert_eq!(emulator.pc, 0x202);
+    }
+
+    #[test]
+    fn reset_clears_a_pending_key_wait() {
+        let mut emulator = key_wait_emulator(Quirks::default());
+        emulator.set_key_press(0x5, true);
+        emulator.tick().unwrap();
+        assert_eq!(emulator.key_wait(), KeyWait::Release(0x5));
+
+        emulator.reset();
+        assert_eq!(emulator.key_wait(), KeyWait::Idle);
+        assert!(!emulator.is_waiting_for_key());
+        assert!(!emulator.is_key_pressed(0x5));
     }
 }
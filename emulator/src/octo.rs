@@ -0,0 +1,428 @@
+use crate::cfg::Cfg;
+use crate::disasm::{self, Instruction};
+use crate::RAM_START_ADDR;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// data bytes written on one line
+const BYTES_PER_LINE: usize = 8;
+
+// What is at an address of the rom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Code(Instruction),
+    Data(u8),
+}
+
+impl Item {
+    fn size(&self) -> u16 {
+        match self {
+            Item::Code(_) => 2,
+            Item::Data(_) => 1,
+        }
+    }
+}
+
+// Octo has no statement for 0nnn, those are written as bytes
+fn is_octo(instruction: Instruction) -> bool {
+    !matches!(instruction, Instruction::Sys(_))
+}
+
+// the rom split into the reachable instructions and the bytes in between,
+// code jumped to at odd addresses overlaps and is written as bytes
+fn items(rom: &[u8]) -> BTreeMap<u16, Item> {
+    let reachable = disasm::reachable(rom);
+    let mut items = BTreeMap::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = (RAM_START_ADDR + offset) as u16;
+        let instruction = rom
+            .get(offset..offset + 2)
+            .filter(|_| reachable.contains(&address))
+            .and_then(|bytes| Instruction::decode(u16::from_be_bytes([bytes[0], bytes[1]])))
+            .filter(|instruction| is_octo(*instruction));
+        let item = match instruction {
+            Some(instruction) => Item::Code(instruction),
+            None => Item::Data(rom[offset]),
+        };
+        items.insert(address, item);
+        offset += item.size() as usize;
+    }
+    items
+}
+
+// the start and the number of rows of the sprites drawn by the code, from
+// the I set before each Dxyn of a block
+fn sprites(rom: &[u8]) -> BTreeMap<u16, u8> {
+    let mut sprites = BTreeMap::new();
+    for block in Cfg::build(rom).blocks.values() {
+        let mut i = None;
+        for line in &block.lines {
+            match line.instruction {
+                Some(Instruction::LdI(nnn)) => i = Some(nnn),
+                // save and load move I with some quirks
+                Some(
+                    Instruction::AddI(_)
+                    | Instruction::LdF(_)
+                    | Instruction::LdIVx(_)
+                    | Instruction::LdVxI(_),
+                ) => i = None,
+                // Dxy0 draws 16x16 sprites, which aren't shown
+                Some(Instruction::Drw(_, _, n)) if n > 0 => {
+                    if let Some(start) = i {
+                        let rows = sprites.entry(start).or_default();
+                        *rows = n.max(*rows);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    sprites
+}
+
+// Writes the rom as Octo source, which assembles back to the same bytes.
+// The reachable code is written as statements and everything else as
+// :byte data, with labels where the code jumps, calls and points I to.
+// The rows of the sprites drawn by the code get a comment with their pixels.
+pub fn to_octo(rom: &[u8]) -> String {
+    let items = items(rom);
+
+    // the targets of the code that have a label, the ones inside of an
+    // instruction or outside of the rom are written as numbers
+    let mut targets = BTreeSet::new();
+    let mut calls = BTreeSet::new();
+    for item in items.values() {
+        match *item {
+            Item::Code(Instruction::Call(nnn)) => {
+                calls.insert(nnn);
+                targets.insert(nnn);
+            }
+            Item::Code(Instruction::Jp(nnn) | Instruction::JpV0(nnn) | Instruction::LdI(nnn)) => {
+                targets.insert(nnn);
+            }
+            _ => (),
+        }
+    }
+    // Octo starts the program at main
+    let mut labels = BTreeMap::from([(RAM_START_ADDR as u16, "main".to_string())]);
+    for nnn in targets {
+        let prefix = match items.get(&nnn) {
+            None => continue,
+            Some(_) if calls.contains(&nnn) => "sub",
+            Some(Item::Code(_)) => "label",
+            Some(Item::Data(_)) => "data",
+        };
+        labels
+            .entry(nnn)
+            .or_insert_with(|| format!("{prefix}_{nnn:03X}"));
+    }
+    let target = |nnn: u16| match labels.get(&nnn) {
+        Some(label) => label.clone(),
+        None => format!("0x{nnn:03X}"),
+    };
+
+    let mut sprite_rows = BTreeSet::new();
+    for (start, rows) in sprites(rom) {
+        sprite_rows.extend((start..start + rows as u16).filter(|row| items.contains_key(row)));
+    }
+
+    let mut out = String::new();
+    _ = writeln!(
+        out,
+        "# disassembled by chip8, assembles back to the same rom"
+    );
+    // bytes of the data line being written
+    let mut data: Vec<u8> = Vec::new();
+    let flush = |out: &mut String, data: &mut Vec<u8>| {
+        if !data.is_empty() {
+            let bytes: Vec<String> = data.iter().map(|b| format!(":byte 0x{b:02X}")).collect();
+            _ = writeln!(out, "    {}", bytes.join(" "));
+            data.clear();
+        }
+    };
+
+    let mut open_if = false;
+    for (&address, item) in &items {
+        if let Some(label) = labels.get(&address) {
+            flush(&mut out, &mut data);
+            _ = writeln!(out, ": {label}");
+        }
+        let instruction = match *item {
+            Item::Data(byte) if sprite_rows.contains(&address) => {
+                flush(&mut out, &mut data);
+                let pixels: String = (0..8)
+                    .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                    .collect();
+                _ = writeln!(out, "    :byte 0x{byte:02X}  # {pixels}");
+                continue;
+            }
+            Item::Data(byte) => {
+                data.push(byte);
+                if data.len() == BYTES_PER_LINE {
+                    flush(&mut out, &mut data);
+                }
+                continue;
+            }
+            Item::Code(instruction) => instruction,
+        };
+        flush(&mut out, &mut data);
+
+        use Instruction::*;
+        let r = |x: u8| format!("v{x:x}");
+        let statement = match instruction {
+            Cls => "clear".to_string(),
+            Ret => "return".to_string(),
+            Jp(nnn) => format!("jump {}", target(nnn)),
+            Call(nnn) => match labels.get(&nnn) {
+                Some(label) => label.clone(),
+                // a call needs a label, the bytes are written instead
+                None => format!(
+                    ":byte 0x{:02X} :byte 0x{:02X}  # call 0x{nnn:03X}",
+                    0x20 | (nnn >> 8),
+                    nnn & 0xFF
+                ),
+            },
+            // the statement after an if runs when the skip isn't taken
+            SeByte(x, kk) => format!("if {} != 0x{kk:02X} then", r(x)),
+            SneByte(x, kk) => format!("if {} == 0x{kk:02X} then", r(x)),
+            SeReg(x, y) => format!("if {} != {} then", r(x), r(y)),
+            SneReg(x, y) => format!("if {} == {} then", r(x), r(y)),
+            Skp(x) => format!("if {} -key then", r(x)),
+            Sknp(x) => format!("if {} key then", r(x)),
+            LdByte(x, kk) => format!("{} := 0x{kk:02X}", r(x)),
+            AddByte(x, kk) => format!("{} += 0x{kk:02X}", r(x)),
+            LdReg(x, y) => format!("{} := {}", r(x), r(y)),
+            Or(x, y) => format!("{} |= {}", r(x), r(y)),
+            And(x, y) => format!("{} &= {}", r(x), r(y)),
+            Xor(x, y) => format!("{} ^= {}", r(x), r(y)),
+            AddReg(x, y) => format!("{} += {}", r(x), r(y)),
+            Sub(x, y) => format!("{} -= {}", r(x), r(y)),
+            Shr(x, y) => format!("{} >>= {}", r(x), r(y)),
+            Subn(x, y) => format!("{} =- {}", r(x), r(y)),
+            Shl(x, y) => format!("{} <<= {}", r(x), r(y)),
+            LdI(nnn) => format!("i := {}", target(nnn)),
+            JpV0(nnn) => format!("jump0 {}", target(nnn)),
+            Rnd(x, kk) => format!("{} := random 0x{kk:02X}", r(x)),
+            Drw(x, y, n) => format!("sprite {} {} {n}", r(x), r(y)),
+            LdVxDt(x) => format!("{} := delay", r(x)),
+            LdVxK(x) => format!("{} := key", r(x)),
+            LdDtVx(x) => format!("delay := {}", r(x)),
+            LdStVx(x) => format!("buzzer := {}", r(x)),
+            AddI(x) => format!("i += {}", r(x)),
+            LdF(x) => format!("i := hex {}", r(x)),
+            LdB(x) => format!("bcd {}", r(x)),
+            LdIVx(x) => format!("save {}", r(x)),
+            LdVxI(x) => format!("load {}", r(x)),
+            Sys(_) => unreachable!("0nnn is written as bytes"),
+        };
+
+        // the statement after an if goes on the same line, when nothing jumps to it
+        let indent = if open_if { "" } else { "    " };
+        let next = address + 2;
+        open_if = matches!(
+            instruction,
+            SeByte(..) | SneByte(..) | SeReg(..) | SneReg(..) | Skp(_) | Sknp(_)
+        ) && matches!(items.get(&next), Some(Item::Code(_)))
+            && !labels.contains_key(&next);
+        if open_if {
+            _ = write!(out, "{indent}{statement} ");
+        } else {
+            _ = writeln!(out, "{indent}{statement}");
+        }
+    }
+    flush(&mut out, &mut data);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // Assembles the part of Octo written by to_octo, a statement for each
+    // token that isn't an operand and a call for the other names
+    fn assemble(source: &str) -> Vec<u8> {
+        let tokens: Vec<&str> = source
+            .lines()
+            .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace())
+            .collect();
+        // the labels are found by the first pass, the sizes don't depend on them
+        let mut labels = HashMap::new();
+        assemble_pass(&tokens, &mut labels);
+        assemble_pass(&tokens, &mut labels)
+    }
+
+    fn assemble_pass<'a>(tokens: &[&'a str], labels: &mut HashMap<&'a str, u16>) -> Vec<u8> {
+        // the sprite heights are decimal
+        let num = |token: &str| match token.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16).expect(token),
+            None => token.parse().expect(token),
+        };
+        let reg = |token: &str| {
+            let x = token
+                .strip_prefix('v')
+                .filter(|x| x.len() == 1)
+                .expect(token);
+            u16::from_str_radix(x, 16).expect(token)
+        };
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            let address = (RAM_START_ADDR + out.len()) as u16;
+            let target = |token: &str| match labels.get(token) {
+                Some(&address) => address,
+                None if token.starts_with("0x") => num(token),
+                None => 0,
+            };
+            let t = &tokens[i..];
+            let (opcode, used) = match t[0] {
+                ":" => {
+                    labels.insert(t[1], address);
+                    i += 2;
+                    continue;
+                }
+                ":byte" => {
+                    out.push(num(t[1]) as u8);
+                    i += 2;
+                    continue;
+                }
+                "clear" => (0x00E0, 1),
+                "return" => (0x00EE, 1),
+                "jump" => (0x1000 | target(t[1]), 2),
+                "jump0" => (0xB000 | target(t[1]), 2),
+                "sprite" => (0xD000 | reg(t[1]) << 8 | reg(t[2]) << 4 | num(t[3]), 4),
+                "bcd" => (0xF033 | reg(t[1]) << 8, 2),
+                "save" => (0xF055 | reg(t[1]) << 8, 2),
+                "load" => (0xF065 | reg(t[1]) << 8, 2),
+                "delay" => (0xF015 | reg(t[2]) << 8, 3),
+                "buzzer" => (0xF018 | reg(t[2]) << 8, 3),
+                "if" => match (t[2], t[3]) {
+                    ("key", _) => (0xE0A1 | reg(t[1]) << 8, 4),
+                    ("-key", _) => (0xE09E | reg(t[1]) << 8, 4),
+                    ("==", y) if y.starts_with('v') => (0x9000 | reg(t[1]) << 8 | reg(y) << 4, 5),
+                    ("!=", y) if y.starts_with('v') => (0x5000 | reg(t[1]) << 8 | reg(y) << 4, 5),
+                    ("==", kk) => (0x4000 | reg(t[1]) << 8 | num(kk), 5),
+                    ("!=", kk) => (0x3000 | reg(t[1]) << 8 | num(kk), 5),
+                    _ => panic!("unknown if {t:?}"),
+                },
+                "i" => match (t[1], t[2]) {
+                    ("+=", x) => (0xF01E | reg(x) << 8, 3),
+                    (":=", "hex") => (0xF029 | reg(t[3]) << 8, 4),
+                    (":=", nnn) => (0xA000 | target(nnn), 3),
+                    _ => panic!("unknown i {t:?}"),
+                },
+                vx if vx.len() == 2 && vx.starts_with('v') => {
+                    let x = reg(vx) << 8;
+                    match (t[1], t[2]) {
+                        (":=", "random") => (0xC000 | x | num(t[3]), 4),
+                        (":=", "delay") => (0xF007 | x, 3),
+                        (":=", "key") => (0xF00A | x, 3),
+                        (operator, y) if y.starts_with('v') => {
+                            let n = match operator {
+                                ":=" => 0x0,
+                                "|=" => 0x1,
+                                "&=" => 0x2,
+                                "^=" => 0x3,
+                                "+=" => 0x4,
+                                "-=" => 0x5,
+                                ">>=" => 0x6,
+                                "=-" => 0x7,
+                                "<<=" => 0xE,
+                                _ => panic!("unknown operator {t:?}"),
+                            };
+                            (0x8000 | x | reg(y) << 4 | n, 3)
+                        }
+                        (":=", kk) => (0x6000 | x | num(kk), 3),
+                        ("+=", kk) => (0x7000 | x | num(kk), 3),
+                        _ => panic!("unknown statement {t:?}"),
+                    }
+                }
+                // a subroutine is called by its name
+                name => (0x2000 | labels.get(name).copied().unwrap_or(0), 1),
+            };
+            out.extend(u16::to_be_bytes(opcode));
+            i += used;
+        }
+        out
+    }
+
+    // the statements written for the rom, without the comments and the labels
+    fn statements(rom: &[u8]) -> Vec<String> {
+        to_octo(rom)
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with(':'))
+            .map(|line| line.trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_roms_round_trip() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../test-roms");
+        let mut roms = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "ch8") {
+                let rom = std::fs::read(&path).unwrap();
+                assert_eq!(assemble(&to_octo(&rom)), rom, "{}", path.display());
+                roms += 1;
+            }
+        }
+        assert!(roms > 0);
+    }
+
+    #[test]
+    fn random_roms_round_trip() {
+        // a xorshift, the same roms on every run
+        let mut state: u32 = 0x2545_F491;
+        for len in 0..200 {
+            let rom: Vec<u8> = (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect();
+            assert_eq!(assemble(&to_octo(&rom)), rom, "{rom:02X?}");
+        }
+    }
+
+    #[test]
+    fn skips_are_written_as_the_opposite_condition() {
+        let cases = [
+            (0x3012, "if v0 != 0x12 then v1 := 0x00"),
+            (0x4012, "if v0 == 0x12 then v1 := 0x00"),
+            (0x5020, "if v0 != v2 then v1 := 0x00"),
+            (0x9020, "if v0 == v2 then v1 := 0x00"),
+            (0xE09E, "if v0 -key then v1 := 0x00"),
+            (0xE0A1, "if v0 key then v1 := 0x00"),
+        ];
+        for (skip, expected) in cases {
+            let [high, low] = u16::to_be_bytes(skip);
+            let rom = [high, low, 0x61, 0x00, 0x12, 0x04];
+            assert_eq!(statements(&rom)[0], expected, "{skip:04X}");
+            assert_eq!(assemble(&to_octo(&rom)), rom);
+        }
+    }
+
+    #[test]
+    fn odd_length_roms_end_with_a_byte() {
+        let rom = [0x60, 0x01, 0x12, 0x02, 0xAB];
+        assert_eq!(
+            statements(&rom),
+            ["v0 := 0x01", "jump label_202", ":byte 0xAB"]
+        );
+        assert_eq!(assemble(&to_octo(&rom)), rom);
+    }
+
+    #[test]
+    fn calls_without_a_label_are_written_as_bytes() {
+        // into the middle of the next instruction and past the end of the rom
+        let rom = [0x22, 0x03, 0x2F, 0xFF, 0x12, 0x04];
+        let statements = statements(&rom);
+        assert_eq!(statements[0], ":byte 0x22 :byte 0x03  # call 0x203");
+        assert_eq!(statements[1], ":byte 0x2F :byte 0xFF  # call 0xFFF");
+        assert_eq!(assemble(&to_octo(&rom)), rom);
+    }
+}
@@ -0,0 +1,262 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    // accepts "#rrggbb", "rrggbb", "#rgb" and "rgb"
+    pub fn from_hex(hex: &str) -> Result<Self, String> {
+        let digits = hex.trim().trim_start_matches('#');
+        let invalid = || format!("Invalid hex color '{hex}'");
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        let channel = |s: &str| u8::from_str_radix(s, 16).map_err(|_| invalid());
+        match digits.len() {
+            6 => Ok(Self::new(
+                channel(&digits[0..2])?,
+                channel(&digits[2..4])?,
+                channel(&digits[4..6])?,
+            )),
+            // each digit is repeated, so "f80" is the same as "ff8800"
+            3 => Ok(Self::new(
+                channel(&digits[0..1])? * 0x11,
+                channel(&digits[1..2])? * 0x11,
+                channel(&digits[2..3])? * 0x11,
+            )),
+            _ => Err(invalid()),
+        }
+    }
+
+    // linear interpolation between two colors, `t` goes from 0.0 to 1.0
+    pub fn mix(self, other: Color, t: f32) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Color::new(
+            lerp(self.r, other.r),
+            lerp(self.g, other.g),
+            lerp(self.b, other.b),
+        )
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+// Colors used to draw the display. The index of a color is the combination
+// of the bit planes lit on a pixel: 0 is the background, 1 the first plane,
+// 2 the second plane and 3 both. The original chip-8 only uses the first two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Color; 4],
+}
+
+impl Palette {
+    pub const CLASSIC: Palette = Palette::new([
+        Color::new(0x00, 0x00, 0x00),
+        Color::new(0xFF, 0xFF, 0xFF),
+        Color::new(0xAA, 0xAA, 0xAA),
+        Color::new(0x55, 0x55, 0x55),
+    ]);
+    pub const GRAY: Palette = Palette::new([
+        Color::new(0x44, 0x44, 0x44),
+        Color::new(0xCC, 0xCC, 0xCC),
+        Color::new(0x99, 0x99, 0x99),
+        Color::new(0x66, 0x66, 0x66),
+    ]);
+    pub const AMBER: Palette = Palette::new([
+        Color::new(0x1A, 0x0F, 0x00),
+        Color::new(0xFF, 0xB0, 0x00),
+        Color::new(0xB3, 0x6B, 0x00),
+        Color::new(0x66, 0x3D, 0x00),
+    ]);
+    pub const GREEN_PHOSPHOR: Palette = Palette::new([
+        Color::new(0x0A, 0x14, 0x0A),
+        Color::new(0x33, 0xFF, 0x66),
+        Color::new(0x1F, 0xAA, 0x44),
+        Color::new(0x14, 0x66, 0x2A),
+    ]);
+    pub const LCD: Palette = Palette::new([
+        Color::new(0x9B, 0xBC, 0x0F),
+        Color::new(0x0F, 0x38, 0x0F),
+        Color::new(0x30, 0x62, 0x30),
+        Color::new(0x6B, 0x8C, 0x1F),
+    ]);
+    pub const OCTO: Palette = Palette::new([
+        Color::new(0x99, 0x66, 0x00),
+        Color::new(0xFF, 0xCC, 0x00),
+        Color::new(0xFF, 0x66, 0x00),
+        Color::new(0x66, 0x22, 0x00),
+    ]);
+
+    pub const BUILT_IN: [(&'static str, Palette); 6] = [
+        ("classic", Palette::CLASSIC),
+        ("gray", Palette::GRAY),
+        ("amber", Palette::AMBER),
+        ("green", Palette::GREEN_PHOSPHOR),
+        ("lcd", Palette::LCD),
+        ("octo", Palette::OCTO),
+    ];
+
+    pub const fn new(colors: [Color; 4]) -> Self {
+        Self { colors }
+    }
+
+    // a two color palette, the colors of the other planes are blended
+    // from the background and the foreground
+    pub fn two_color(background: Color, foreground: Color) -> Self {
+        Self::new([
+            background,
+            foreground,
+            background.mix(foreground, 2.0 / 3.0),
+            background.mix(foreground, 1.0 / 3.0),
+        ])
+    }
+
+    pub fn by_name(name: &str) -> Option<Palette> {
+        Palette::BUILT_IN
+            .into_iter()
+            .find(|(palette_name, _)| palette_name.eq_ignore_ascii_case(name))
+            .map(|(_, palette)| palette)
+    }
+
+    pub fn background(&self) -> Color {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> Color {
+        self.colors[1]
+    }
+
+    // color of a pixel with the given intensity, from 0.0 (background)
+    // to 1.0 (foreground), used by the phosphor persistence filter
+    pub fn shade(&self, intensity: f32) -> Color {
+        self.background().mix(self.foreground(), intensity)
+    }
+
+    // color of a pixel from its lit planes
+    pub fn color(&self, planes: u8) -> Color {
+        self.colors[(planes & 0b11) as usize]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::CLASSIC
+    }
+}
+
+// Parses either the name of a built-in palette or a comma separated list of
+// 2 or 4 hex colors, starting with the background, e.g "#000,#fff"
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(palette) = Palette::by_name(s) {
+            return Ok(palette);
+        }
+
+        if !s.contains(',') {
+            let names: Vec<_> = Palette::BUILT_IN.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "Unknown palette '{s}', expected one of: {} or a list of hex colors",
+                names.join(", ")
+            ));
+        }
+
+        let colors = s
+            .split(',')
+            .map(Color::from_hex)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match colors[..] {
+            [background, foreground] => Ok(Palette::two_color(background, foreground)),
+            [a, b, c, d] => Ok(Palette::new([a, b, c, d])),
+            _ => Err(format!(
+                "A palette needs 2 or 4 colors, but {} were given",
+                colors.len()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_colors() {
+        let orange = Color::new(0xFF, 0x88, 0x00);
+        assert_eq!(Color::from_hex("#ff8800"), Ok(orange));
+        assert_eq!(Color::from_hex("FF8800"), Ok(orange));
+        assert_eq!(Color::from_hex("#f80"), Ok(orange));
+        assert_eq!(Color::from_hex(" f80 "), Ok(orange));
+        for invalid in [
+            "", "#", "#ff", "#ff88", "#ff880", "#ff88000", "#gg8800", "#+f+f+f",
+        ] {
+            assert!(Color::from_hex(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn palettes_by_name() {
+        for (name, palette) in Palette::BUILT_IN {
+            assert_eq!(name.parse(), Ok(palette));
+            assert_eq!(name.to_ascii_uppercase().parse(), Ok(palette));
+        }
+        let error = "sepia".parse::<Palette>().unwrap_err();
+        assert!(error.starts_with("Unknown palette 'sepia'"), "{error}");
+    }
+
+    #[test]
+    fn palettes_of_colors() {
+        let black = Color::new(0, 0, 0);
+        let white = Color::new(0xFF, 0xFF, 0xFF);
+        assert_eq!("#000,#fff".parse(), Ok(Palette::two_color(black, white)));
+        assert_eq!("#000,#fff,#aaa,#555".parse(), Ok(Palette::CLASSIC));
+        for count in ["#000", "#000,#fff,#aaa", "#000,#fff,#aaa,#555,#222"] {
+            assert!(count.parse::<Palette>().is_err(), "{count}");
+        }
+        assert!("#000,#ffz".parse::<Palette>().is_err());
+    }
+
+    #[test]
+    fn two_color_palettes_blend_the_other_planes() {
+        let palette = Palette::two_color(Color::new(0, 0, 0), Color::new(0x99, 0x99, 0x99));
+        assert_eq!(palette.color(2), Color::new(0x66, 0x66, 0x66));
+        assert_eq!(palette.color(3), Color::new(0x33, 0x33, 0x33));
+    }
+
+    #[test]
+    fn built_in_colors_are_distinct() {
+        // each channel of the planes differs from the background by a visible amount
+        for (name, palette) in Palette::BUILT_IN {
+            let background = palette.background();
+            for planes in 1..4 {
+                let color = palette.color(planes);
+                let distance = [
+                    color.r.abs_diff(background.r),
+                    color.g.abs_diff(background.g),
+                    color.b.abs_diff(background.b),
+                ];
+                assert!(
+                    distance.iter().any(|&d| d >= 0x20),
+                    "{name} plane {planes} is too close to the background"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use std::fmt;
+use std::str::FromStr;
+
+// Chip-8 games move sprites by erasing and redrawing them with XOR, so a
+// sprite is often missing in the frame that gets shown, which flickers.
+// Those modes smooth that by keeping some light on recently lit pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Persistence {
+    // draw the display as it is
+    #[default]
+    Off,
+    // lit pixels fade out over the given number of frames
+    Fade(u8),
+    // average of the last two frames
+    Blend,
+}
+
+impl fmt::Display for Persistence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Persistence::Off => write!(f, "off"),
+            Persistence::Fade(frames) => write!(f, "{frames}"),
+            Persistence::Blend => write!(f, "blend"),
+        }
+    }
+}
+
+// "off", "blend" or the number of frames a pixel takes to fade out
+impl FromStr for Persistence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" | "0" => Ok(Persistence::Off),
+            "blend" => Ok(Persistence::Blend),
+            _ => s.parse().map(Persistence::Fade).map_err(|_| {
+                format!("Invalid persistence '{s}', expected off, blend or a number of frames")
+            }),
+        }
+    }
+}
+
+// Post process over the display buffer, keeps an intensity from 0.0 to 1.0
+// for each pixel that frontends use to blend the background and foreground
+pub struct PhosphorFilter {
+    mode: Persistence,
+    intensity: Vec<f32>,
+    previous: Vec<bool>,
+}
+
+impl PhosphorFilter {
+    pub fn new(mode: Persistence) -> Self {
+        Self {
+            mode,
+            intensity: vec![0.0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            previous: vec![false; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+        }
+    }
+
+    pub fn mode(&self) -> Persistence {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Persistence) {
+        self.mode = mode;
+    }
+
+    // should be called once per frame (60hz) with the current display
+    pub fn update(&mut self, display: &[bool]) {
+        if self.intensity.len() != display.len() {
+            self.intensity = vec![0.0; display.len()];
+            self.previous = vec![false; display.len()];
+        }
+
+        match self.mode {
+            Persistence::Off => {
+                for (intensity, pixel) in self.intensity.iter_mut().zip(display) {
+                    *intensity = *pixel as u8 as f32;
+                }
+            }
+            Persistence::Fade(frames) => {
+                let step = 1.0 / frames.max(1) as f32;
+                for (intensity, pixel) in self.intensity.iter_mut().zip(display) {
+                    *intensity = if *pixel {
+                        1.0
+                    } else {
+                        (*intensity - step).max(0.0)
+                    };
+                }
+            }
+            Persistence::Blend => {
+                let pixels = display.iter().zip(&self.previous);
+                for (intensity, (pixel, previous)) in self.intensity.iter_mut().zip(pixels) {
+                    *intensity = (*pixel as u8 + *previous as u8) as f32 / 2.0;
+                }
+            }
+        }
+        self.previous.copy_from_slice(display);
+    }
+
+    // intensity of each pixel, in the same layout as the display
+    pub fn intensity(&self) -> &[f32] {
+        &self.intensity
+    }
+
+    pub fn clear(&mut self) {
+        self.intensity.fill(0.0);
+        self.previous.fill(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn off_is_the_display() {
+        let mut filter = PhosphorFilter::new(Persistence::Off);
+        filter.update(&[true, false, true]);
+        assert_eq!(filter.intensity(), [1.0, 0.0, 1.0]);
+        filter.update(&[false, true, false]);
+        assert_eq!(filter.intensity(), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn fade_decays_over_the_frames() {
+        let mut filter = PhosphorFilter::new(Persistence::Fade(4));
+        filter.update(&[true, false]);
+        assert_eq!(filter.intensity(), [1.0, 0.0]);
+        for expected in [0.75, 0.5, 0.25, 0.0, 0.0] {
+            filter.update(&[false, false]);
+            assert_eq!(filter.intensity(), [expected, 0.0]);
+        }
+        // lit again at full intensity
+        filter.update(&[true, false]);
+        assert_eq!(filter.intensity(), [1.0, 0.0]);
+    }
+
+    #[test]
+    fn blend_averages_two_frames() {
+        let mut filter = PhosphorFilter::new(Persistence::Blend);
+        filter.update(&[true, true, false, false]);
+        assert_eq!(filter.intensity(), [0.5, 0.5, 0.0, 0.0]);
+        filter.update(&[true, false, true, false]);
+        assert_eq!(filter.intensity(), [1.0, 0.5, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn clear_forgets_the_previous_frames() {
+        let mut filter = PhosphorFilter::new(Persistence::Blend);
+        filter.update(&[true]);
+        filter.clear();
+        assert_eq!(filter.intensity(), [0.0]);
+        filter.update(&[false]);
+        assert_eq!(filter.intensity(), [0.0]);
+    }
+
+    #[test]
+    fn parses_the_modes() {
+        assert_eq!("off".parse(), Ok(Persistence::Off));
+        assert_eq!("0".parse(), Ok(Persistence::Off));
+        assert_eq!("blend".parse(), Ok(Persistence::Blend));
+        assert_eq!("6".parse(), Ok(Persistence::Fade(6)));
+        assert!("fast".parse::<Persistence>().is_err());
+        assert!("-1".parse::<Persistence>().is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::quirks::Quirks;
+use std::fmt;
+use std::str::FromStr;
+
+// The chip-8 interpreters roms were written for, with the ids of the
+// chip-8 database (https://github.com/chip-8/chip-8-database).
+// Only the chip-8 instructions are emulated, the platforms with more
+// instructions are known so their roms can be recognized and their
+// quirks applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Platform {
+    OriginalChip8,
+    HybridVip,
+    ModernChip8,
+    Chip8X,
+    Chip48,
+    SuperChip1,
+    SuperChip,
+    MegaChip8,
+    XoChip,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 9] = [
+        Platform::OriginalChip8,
+        Platform::HybridVip,
+        Platform::ModernChip8,
+        Platform::Chip8X,
+        Platform::Chip48,
+        Platform::SuperChip1,
+        Platform::SuperChip,
+        Platform::MegaChip8,
+        Platform::XoChip,
+    ];
+
+    // id in the chip-8 database
+    pub fn id(&self) -> &'static str {
+        match self {
+            Platform::OriginalChip8 => "originalChip8",
+            Platform::HybridVip => "hybridVIP",
+            Platform::ModernChip8 => "modernChip8",
+            Platform::Chip8X => "chip8x",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip1 => "superchip1",
+            Platform::SuperChip => "superchip",
+            Platform::MegaChip8 => "megachip8",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::OriginalChip8 => "CHIP-8 (COSMAC VIP)",
+            Platform::HybridVip => "CHIP-8 with machine code (COSMAC VIP)",
+            Platform::ModernChip8 => "Modern CHIP-8",
+            Platform::Chip8X => "CHIP-8X",
+            Platform::Chip48 => "CHIP-48",
+            Platform::SuperChip1 => "SUPER-CHIP 1.0",
+            Platform::SuperChip => "SUPER-CHIP 1.1",
+            Platform::MegaChip8 => "MEGA-CHIP",
+            Platform::XoChip => "XO-CHIP",
+        }
+    }
+
+    // instructions per 60hz frame
+    pub fn default_tickrate(&self) -> u32 {
+        match self {
+            Platform::OriginalChip8 | Platform::HybridVip | Platform::Chip8X => 15,
+            Platform::ModernChip8 => 12,
+            Platform::Chip48 | Platform::SuperChip1 | Platform::SuperChip => 30,
+            Platform::MegaChip8 => 1000,
+            Platform::XoChip => 100,
+        }
+    }
+
+    // the quirks of the platform in the database, the display wait isn't
+    // emulated and incrementing I by X is approximated by X + 1
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::OriginalChip8 | Platform::HybridVip | Platform::Chip8X => Quirks::VIP,
+            Platform::ModernChip8 => Quirks {
+                vf_reset: false,
+                ..Quirks::VIP
+            },
+            Platform::Chip48 | Platform::SuperChip1 => Quirks {
+                memory_increment_i: true,
+                ..Quirks::SCHIP
+            },
+            Platform::SuperChip | Platform::MegaChip8 => Quirks::SCHIP,
+            Platform::XoChip => Quirks::XO_CHIP,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Platform::ALL
+            .into_iter()
+            .find(|platform| platform.id().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown platform '{s}'"))
+    }
+}
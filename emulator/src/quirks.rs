@@ -0,0 +1,162 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Behaviors that differ between chip-8 implementations, some roms
+// depend on one or the other. The default is how this emulator always
+// worked, close to what most modern games expect.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quirks {
+    // 8xy1, 8xy2 and 8xy3 set VF to 0, like the COSMAC VIP
+    pub vf_reset: bool,
+    // 8xy6 and 8xyE shift Vy into Vx, instead of shifting Vx in place
+    pub shift_vy: bool,
+    // Fx55 and Fx65 leave I after the last register, like the COSMAC VIP
+    pub memory_increment_i: bool,
+    // Bnnn jumps to nnn + Vx with x the high nibble of nnn, like the SUPER-CHIP
+    pub jump_vx: bool,
+    // sprites are cut at the edges of the display instead of wrapping around
+    pub clip_sprites: bool,
+    // FX0A completes as soon as a key is down, instead of waiting for it to
+    // be released like the COSMAC VIP. A held key then satisfies every FX0A
+    // in a row, which is how this emulator used to work.
+    pub key_wait_on_press: bool,
+}
+
+// names used by FromStr and Display
+const QUIRK_NAMES: [&str; 6] = [
+    "vf_reset",
+    "shift_vy",
+    "memory_increment_i",
+    "jump_vx",
+    "clip_sprites",
+    "key_wait_on_press",
+];
+
+impl Quirks {
+    // the original interpreter of the COSMAC VIP
+    pub const VIP: Quirks = Quirks {
+        vf_reset: true,
+        shift_vy: true,
+        memory_increment_i: true,
+        jump_vx: false,
+        clip_sprites: true,
+        key_wait_on_press: false,
+    };
+
+    // SUPER-CHIP 1.1 on the HP48
+    pub const SCHIP: Quirks = Quirks {
+        vf_reset: false,
+        shift_vy: false,
+        memory_increment_i: false,
+        jump_vx: true,
+        clip_sprites: true,
+        key_wait_on_press: false,
+    };
+
+    // XO-CHIP, as implemented by Octo
+    pub const XO_CHIP: Quirks = Quirks {
+        vf_reset: false,
+        shift_vy: true,
+        memory_increment_i: true,
+        jump_vx: false,
+        clip_sprites: false,
+        key_wait_on_press: false,
+    };
+
+    pub const PRESETS: [(&'static str, Quirks); 5] = [
+        ("default", Quirks::DEFAULT),
+        ("chip8", Quirks::VIP),
+        ("vip", Quirks::VIP),
+        ("schip", Quirks::SCHIP),
+        ("xo-chip", Quirks::XO_CHIP),
+    ];
+
+    const DEFAULT: Quirks = Quirks {
+        vf_reset: false,
+        shift_vy: false,
+        memory_increment_i: false,
+        jump_vx: false,
+        clip_sprites: false,
+        key_wait_on_press: false,
+    };
+
+    pub fn preset(name: &str) -> Option<Quirks> {
+        Quirks::PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, quirks)| *quirks)
+    }
+
+    fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "vf_reset" => Some(&mut self.vf_reset),
+            "shift_vy" => Some(&mut self.shift_vy),
+            "memory_increment_i" => Some(&mut self.memory_increment_i),
+            "jump_vx" => Some(&mut self.jump_vx),
+            "clip_sprites" => Some(&mut self.clip_sprites),
+            "key_wait_on_press" => Some(&mut self.key_wait_on_press),
+            _ => None,
+        }
+    }
+
+    fn flags(&self) -> [bool; 6] {
+        [
+            self.vf_reset,
+            self.shift_vy,
+            self.memory_increment_i,
+            self.jump_vx,
+            self.clip_sprites,
+            self.key_wait_on_press,
+        ]
+    }
+}
+
+// the quirks enabled, comma separated, or "none"
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let enabled: Vec<_> = QUIRK_NAMES
+            .iter()
+            .zip(self.flags())
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect();
+        if enabled.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", enabled.join(","))
+        }
+    }
+}
+
+// A comma separated list of presets and quirks, applied in order.
+// Quirks are enabled by name and disabled with a "no-" prefix,
+// e.g "schip,no-clip_sprites" or "vf_reset,shift_vy".
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut quirks = Quirks::default();
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            if item == "none" {
+                quirks = Quirks::DEFAULT;
+            } else if let Some(preset) = Quirks::preset(item) {
+                quirks = preset;
+            } else {
+                let (name, enabled) = match item.strip_prefix("no-") {
+                    Some(name) => (name, false),
+                    None => (item, true),
+                };
+                let Some(flag) = quirks.flag_mut(&name.replace('-', "_")) else {
+                    let presets: Vec<_> = Quirks::PRESETS.iter().map(|(name, _)| *name).collect();
+                    return Err(format!(
+                        "Unknown quirk '{item}', expected a preset ({}) or a quirk ({})",
+                        presets.join(", "),
+                        QUIRK_NAMES.join(", ")
+                    ));
+                };
+                *flag = enabled;
+            }
+        }
+        Ok(quirks)
+    }
+}
@@ -0,0 +1,128 @@
+use crate::palette::{Color, Palette};
+use crate::phosphor::PhosphorFilter;
+use crate::{Emulator, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+pub const BYTES_PER_PIXEL: usize = 4; // R, G, B, A
+
+// size in bytes of a RGBA buffer holding the display at the given scale
+pub fn rgba_buffer_size(scale: usize) -> usize {
+    DISPLAY_WIDTH * scale * DISPLAY_HEIGHT * scale * BYTES_PER_PIXEL
+}
+
+// Writes the display into `out` as packed RGBA rows, each chip-8 pixel
+// becoming a `scale` x `scale` square. Panics if `out` is smaller than
+// `rgba_buffer_size(scale)`.
+pub fn render_rgba(display: &[bool], palette: &Palette, scale: usize, out: &mut [u8]) {
+    render_with(scale, out, |idx| palette.color(display[idx] as u8));
+}
+
+// Same as `render_rgba`, but from the pixel intensities of a phosphor filter.
+// Panics the same way when `out` is too small.
+pub fn render_intensity_rgba(intensity: &[f32], palette: &Palette, scale: usize, out: &mut [u8]) {
+    render_with(scale, out, |idx| palette.shade(intensity[idx]));
+}
+
+fn render_with(scale: usize, out: &mut [u8], color_at: impl Fn(usize) -> Color) {
+    assert!(
+        out.len() >= rgba_buffer_size(scale),
+        "RGBA buffer too small for the display at scale {scale}"
+    );
+
+    let row_size = DISPLAY_WIDTH * scale * BYTES_PER_PIXEL;
+    for y in 0..DISPLAY_HEIGHT {
+        // draw the first line of the row, then copy it for the rest of the scale
+        let row_start = y * scale * row_size;
+        let row = &mut out[row_start..row_start + row_size];
+        for x in 0..DISPLAY_WIDTH {
+            let color = color_at(x + y * DISPLAY_WIDTH);
+            let rgba = [color.r, color.g, color.b, 0xFF];
+            let start = x * scale * BYTES_PER_PIXEL;
+            row[start..start + scale * BYTES_PER_PIXEL]
+                .chunks_exact_mut(BYTES_PER_PIXEL)
+                .for_each(|pixel| pixel.copy_from_slice(&rgba));
+        }
+
+        for line in 1..scale {
+            let start = row_start + line * row_size;
+            out.copy_within(row_start..row_start + row_size, start);
+        }
+    }
+}
+
+impl Emulator {
+    pub fn render_rgba(&self, palette: &Palette, scale: usize, out: &mut [u8]) {
+        render_rgba(&self.display, palette, scale, out);
+    }
+}
+
+impl PhosphorFilter {
+    pub fn render_rgba(&self, palette: &Palette, scale: usize, out: &mut [u8]) {
+        render_intensity_rgba(self.intensity(), palette, scale, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALE: usize = 3;
+
+    // the RGBA of the pixel of the scaled buffer at x, y
+    fn pixel(out: &[u8], x: usize, y: usize) -> [u8; 4] {
+        let start = (y * DISPLAY_WIDTH * SCALE + x) * BYTES_PER_PIXEL;
+        out[start..start + BYTES_PER_PIXEL].try_into().unwrap()
+    }
+
+    fn rgba(color: Color) -> [u8; 4] {
+        [color.r, color.g, color.b, 0xFF]
+    }
+
+    #[test]
+    fn buffer_size() {
+        assert_eq!(rgba_buffer_size(1), 64 * 32 * 4);
+        assert_eq!(rgba_buffer_size(SCALE), 64 * 3 * 32 * 3 * 4);
+    }
+
+    #[test]
+    fn pixels_become_scaled_squares() {
+        let palette = Palette::CLASSIC;
+        let mut display = [false; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        // the pixel at 1, 2 and the last one
+        display[1 + 2 * DISPLAY_WIDTH] = true;
+        display[DISPLAY_WIDTH * DISPLAY_HEIGHT - 1] = true;
+        let mut out = vec![0; rgba_buffer_size(SCALE)];
+        render_rgba(&display, &palette, SCALE, &mut out);
+
+        let (background, foreground) = (rgba(palette.background()), rgba(palette.foreground()));
+        for y in 0..DISPLAY_HEIGHT * SCALE {
+            for x in 0..DISPLAY_WIDTH * SCALE {
+                let lit = (x / SCALE, y / SCALE) == (1, 2)
+                    || (x / SCALE, y / SCALE) == (DISPLAY_WIDTH - 1, DISPLAY_HEIGHT - 1);
+                let expected = if lit { foreground } else { background };
+                assert_eq!(pixel(&out, x, y), expected, "{x}, {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn intensities_are_shades_of_the_palette() {
+        let palette = Palette::two_color(Color::new(0, 0, 0), Color::new(0xFF, 0xFF, 0xFF));
+        let mut intensity = [0.0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        intensity[0] = 1.0;
+        intensity[1] = 0.5;
+        let mut out = vec![0; rgba_buffer_size(SCALE)];
+        render_intensity_rgba(&intensity, &palette, SCALE, &mut out);
+
+        assert_eq!(pixel(&out, 0, 0), [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(pixel(&out, SCALE, SCALE - 1), [0x80, 0x80, 0x80, 0xFF]);
+        assert_eq!(pixel(&out, 2 * SCALE, 0), [0, 0, 0, 0xFF]);
+    }
+
+    #[test]
+    #[should_panic(expected = "RGBA buffer too small")]
+    fn small_buffers_panic() {
+        let intensity = [0.0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        let mut out = vec![0; rgba_buffer_size(1)];
+        render_intensity_rgba(&intensity, &Palette::CLASSIC, 2, &mut out);
+    }
+}
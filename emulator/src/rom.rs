@@ -0,0 +1,7 @@
+use sha1::{Digest, Sha1};
+
+// SHA-1 of the rom as a lowercase hex string, used to identify a rom
+// in the per rom settings, the same way the chip-8 database does
+pub fn sha1_hex(rom: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(rom))
+}
@@ -0,0 +1,222 @@
+use crate::audio::{Buzzer, BuzzerConfig};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// the timers of the chip-8 are decremented at 60hz
+pub const FRAME_RATE: u32 = 60;
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 1;
+const BITS_PER_SAMPLE: u16 = 16;
+
+// Writes a mono 16 bit PCM wav file. The sizes in the header are only
+// known at the end, so they are patched by `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        write_header(&mut writer, sample_rate, 0)?;
+        Ok(Self {
+            writer,
+            sample_rate,
+            data_size: 0,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // samples are expected to be in the range -1.0..=1.0
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size += (samples.len() * 2) as u32;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.writer, self.sample_rate, self.data_size)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn write_header(writer: &mut impl Write, sample_rate: u32, data_size: u32) -> io::Result<()> {
+    let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+    let byte_rate = sample_rate * block_align as u32;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?; // size of the fmt chunk
+    writer.write_all(&1u16.to_le_bytes())?; // PCM format
+    writer.write_all(&CHANNELS.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&byte_rate.to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    Ok(())
+}
+
+// Renders the buzzer from the state of the sound timer, one emulated frame
+// at a time, so the recording doesn't depend on the real time playback.
+pub struct AudioRecorder<W: Write + Seek> {
+    buzzer: Buzzer,
+    wav: WavWriter<W>,
+    frames: u64,
+    samples_written: u64,
+    buffer: Vec<f32>,
+}
+
+impl AudioRecorder<BufWriter<File>> {
+    pub fn create(
+        path: impl AsRef<Path>,
+        config: BuzzerConfig,
+        sample_rate: u32,
+    ) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Self::new(file, config, sample_rate)
+    }
+}
+
+impl<W: Write + Seek> AudioRecorder<W> {
+    pub fn new(writer: W, config: BuzzerConfig, sample_rate: u32) -> io::Result<Self> {
+        Ok(Self {
+            buzzer: Buzzer::new(config, sample_rate),
+            wav: WavWriter::new(writer, sample_rate)?,
+            frames: 0,
+            samples_written: 0,
+            buffer: Vec::new(),
+        })
+    }
+
+    // records one 60hz frame, `sound_on` is the state of the sound timer
+    // during that frame
+    pub fn record_frame(&mut self, sound_on: bool) -> io::Result<()> {
+        self.frames += 1;
+        // computing the total from the frame count keeps the recording
+        // sample accurate even when the rate isn't a multiple of 60
+        let total = self.frames * self.wav.sample_rate() as u64 / FRAME_RATE as u64;
+        let count = (total - self.samples_written) as usize;
+
+        self.buzzer.set_active(sound_on);
+        self.buffer.resize(count, 0.0);
+        self.buzzer.fill(&mut self.buffer);
+        self.wav.write_samples(&self.buffer)?;
+        self.samples_written = total;
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        self.wav.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    // the samples of a finished recording
+    fn samples(bytes: &[u8]) -> Vec<i16> {
+        bytes[HEADER_SIZE as usize..]
+            .chunks(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect()
+    }
+
+    // the number of samples written for each frame
+    fn samples_per_frame(sample_rate: u32, frames: usize) -> Vec<usize> {
+        let writer = Cursor::new(Vec::new());
+        let mut recorder =
+            AudioRecorder::new(writer, BuzzerConfig::default(), sample_rate).unwrap();
+        let mut written = 0;
+        let mut counts = Vec::new();
+        for _ in 0..frames {
+            recorder.record_frame(false).unwrap();
+            counts.push(recorder.wav.data_size as usize / 2 - written);
+            written = recorder.wav.data_size as usize / 2;
+        }
+        counts
+    }
+
+    #[test]
+    fn header_has_the_sizes_after_finish() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 22050).unwrap();
+        wav.write_samples(&[0.0, 1.0, -1.0]).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4), 36 + 6);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&bytes, 16), 16);
+        // PCM, mono
+        assert_eq!(&bytes[20..24], &[1, 0, 1, 0]);
+        assert_eq!(u32_at(&bytes, 24), 22050);
+        assert_eq!(u32_at(&bytes, 28), 22050 * 2);
+        // 2 bytes per sample, 16 bits
+        assert_eq!(&bytes[32..36], &[2, 0, 16, 0]);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 6);
+        assert_eq!(samples(&bytes), [0, i16::MAX, -i16::MAX]);
+    }
+
+    #[test]
+    fn frames_have_the_samples_of_a_60th_of_a_second() {
+        assert_eq!(samples_per_frame(44100, 4), [735; 4]);
+        // the fractions are carried over to the next frames
+        assert_eq!(samples_per_frame(22050, 4), [367, 368, 367, 368]);
+        assert_eq!(samples_per_frame(44000, 6), [733, 733, 734, 733, 733, 734]);
+        assert_eq!(samples_per_frame(44000, 60).iter().sum::<usize>(), 44000);
+    }
+
+    #[test]
+    fn tone_follows_the_sound_timer() {
+        let config = BuzzerConfig {
+            attack: 0.0,
+            release: 0.0,
+            ..BuzzerConfig::default()
+        };
+        let writer = Cursor::new(Vec::new());
+        let mut recorder = AudioRecorder::new(writer, config, 44100).unwrap();
+        let timer = [false, true, true, false, true, false];
+        for sound_on in timer {
+            recorder.record_frame(sound_on).unwrap();
+        }
+        assert_eq!(recorder.frames(), timer.len() as u64);
+        let bytes = recorder.finish().unwrap().into_inner();
+
+        let samples = samples(&bytes);
+        assert_eq!(samples.len(), timer.len() * 735);
+        for (frame, (samples, sound_on)) in samples.chunks(735).zip(timer).enumerate() {
+            // the square wave is never 0 while it plays
+            let playing = samples.iter().all(|&sample| sample != 0);
+            let silent = samples.iter().all(|&sample| sample == 0);
+            assert!(if sound_on { playing } else { silent }, "frame {frame}");
+        }
+    }
+}
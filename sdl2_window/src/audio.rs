@@ -1,23 +1,19 @@
+use emulator::audio::Buzzer;
 use sdl2::audio::{AudioCallback, AudioSpecDesired};
 
 pub struct SoundWave {
-    pub phase_inc: f32,
-    pub phase: f32,
-    pub volume: f32,
+    pub buzzer: Buzzer,
 }
 
 impl AudioCallback for SoundWave {
     type Channel = f32;
     fn callback(&mut self, out: &mut [Self::Channel]) {
-        for x in out.iter_mut() {
-            *x = if self.phase < 0.5 { self.volume } else { -self.volume };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-        }
+        self.buzzer.fill(out);
     }
 }
 
 pub const DESIRED_SPEC: AudioSpecDesired = AudioSpecDesired {
     freq: Some(44100),
-    channels: Some(1), 
-    samples: None, 
+    channels: Some(1),
+    samples: None,
 };
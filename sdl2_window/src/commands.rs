@@ -0,0 +1,219 @@
+// The commands that don't open a window
+use crate::config;
+use crate::options::{ticks_per_frame, DecompileOptions, DisasmOptions, TestOptions};
+use emulator::cfg::Cfg;
+use emulator::decompile;
+use emulator::detect;
+use emulator::disasm;
+use emulator::inspect;
+use emulator::octo;
+use emulator::wav::AudioRecorder;
+use emulator::{Emulator, Fault, DISPLAY_HEIGHT, DISPLAY_WIDTH, MAX_ROM_SIZE};
+use std::path::Path;
+
+const FRAME_RATE: u32 = 60;
+pub const RECORD_SAMPLE_RATE: u32 = 44100;
+// lines of evidence of the detected platform printed by info
+const MAX_EVIDENCE: usize = 5;
+
+pub fn read_rom(path: &Path) -> Result<Vec<u8>, String> {
+    let rom = std::fs::read(path)
+        .map_err(|error| format!("Error reading the rom {}: {error}", path.display()))?;
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(format!(
+            "The rom {} has {} bytes, the maximum is {MAX_ROM_SIZE}",
+            path.display(),
+            rom.len()
+        ));
+    }
+    Ok(rom)
+}
+
+pub fn info(path: &Path) -> Result<(), String> {
+    let rom = read_rom(path)?;
+    let report = inspect::inspect(&rom);
+    println!("File:      {}", path.display());
+    println!("Size:      {} bytes", report.size);
+    println!("SHA-1:     {}", report.sha1);
+
+    let database = config::load_database()?;
+    match database.lookup(&report.sha1) {
+        Some(info) => {
+            println!("Title:     {}", info.title);
+            if !info.authors.is_empty() {
+                println!("By:        {}", info.authors.join(", "));
+            }
+            if let Some(platform) = info.platform {
+                println!("For:       {platform}");
+            }
+        }
+        None => println!("Title:     not in the rom database"),
+    }
+
+    let detection = &report.detection;
+    println!(
+        "Detected:  {}, {:.0}% sure, quirks {}",
+        detection.platform,
+        detection.confidence * 100.0,
+        detection.quirks
+    );
+    for evidence in detection.evidence.iter().take(MAX_EVIDENCE) {
+        println!("           {evidence}");
+    }
+    if detection.evidence.len() > MAX_EVIDENCE {
+        println!(
+            "           and {} more",
+            detection.evidence.len() - MAX_EVIDENCE
+        );
+    }
+
+    println!(
+        "Code:      {} of {} bytes reachable from the start ({:.0}%)",
+        report.reachable_bytes,
+        report.size,
+        report.reachable_ratio() * 100.0
+    );
+
+    let keys = &report.keys;
+    let mut key_uses = Vec::new();
+    if !keys.checked.is_empty() {
+        let checked: Vec<_> = keys.checked.iter().map(|key| format!("{key:X}")).collect();
+        key_uses.push(format!("checks {}", checked.join(", ")));
+    }
+    if keys.computed_checks > 0 {
+        key_uses.push(format!("{} checks of computed keys", keys.computed_checks));
+    }
+    if keys.waits > 0 {
+        key_uses.push(format!("waits for a key in {} places", keys.waits));
+    }
+    if key_uses.is_empty() {
+        key_uses.push("none".to_string());
+    }
+    println!("Keys:      {}", key_uses.join(", "));
+
+    if report.invalid.is_empty() {
+        println!("Invalid:   none");
+    } else {
+        println!("Invalid:   opcodes this emulator can't run");
+        for line in &report.invalid {
+            println!("           {line}");
+        }
+    }
+
+    println!("Instructions:");
+    for (mnemonic, count) in &report.histogram {
+        println!("  {mnemonic:<20} {count:>5}");
+    }
+    Ok(())
+}
+
+pub fn disasm(options: &DisasmOptions) -> Result<(), String> {
+    let rom = read_rom(&options.rom_path)?;
+    if options.octo {
+        print!("{}", octo::to_octo(&rom));
+        return Ok(());
+    }
+    for line in disasm::disassemble(&rom) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+pub fn cfg(path: &Path) -> Result<(), String> {
+    let rom = read_rom(path)?;
+    let cfg = Cfg::build(&rom);
+    print!("{}", cfg.to_dot());
+    // on stderr, so the output can still be piped into dot
+    for block in cfg.computed_jumps() {
+        if let Some(line) = block.lines.last() {
+            eprintln!("note: the target of {line} is unknown");
+        }
+    }
+    Ok(())
+}
+
+pub fn decompile(options: &DecompileOptions) -> Result<(), String> {
+    let rom = read_rom(&options.rom_path)?;
+    print!("{}", decompile::decompile(&rom, options.style));
+    Ok(())
+}
+
+pub fn test(options: &TestOptions) -> Result<(), String> {
+    let rom = read_rom(&options.rom_path)?;
+
+    let (settings, _) = config::settings_for_rom(
+        &rom,
+        options.config.as_deref(),
+        options.settings.clone(),
+    )?;
+
+    let mut recorder = match &options.record_audio {
+        Some(path) => Some(
+            AudioRecorder::create(path, settings.buzzer(), RECORD_SAMPLE_RATE)
+                .map_err(|error| format!("Error creating the audio recording file: {error}"))?,
+        ),
+        None => None,
+    };
+
+    let mut chip8 = Emulator::new();
+    chip8.set_quirks(settings.quirks());
+    chip8.load_rom(&rom)?;
+
+    let ticks_per_frame = ticks_per_frame(settings.ips(), FRAME_RATE);
+    for _ in 0..options.frames {
+        for _ in 0..ticks_per_frame {
+            chip8.tick().map_err(fault_error)?;
+        }
+        chip8.tick_delay_timer();
+        chip8.tick_sound_timer();
+
+        if let Some(recorder) = &mut recorder {
+            recorder
+                .record_frame(chip8.is_sound_playing())
+                .map_err(|error| format!("Error recording the audio: {error}"))?;
+        }
+    }
+
+    if let Some(recorder) = recorder {
+        recorder
+            .finish()
+            .map_err(|error| format!("Error finishing the audio recording: {error}"))?;
+    }
+
+    print!("{}", display_to_text(&chip8.display));
+    Ok(())
+}
+
+// the error of an instruction the emulator can't run, with the platform
+// of the unknown opcodes
+pub fn fault_error(fault: Fault) -> String {
+    let Fault::UnknownOpcode { opcode, .. } = fault else {
+        return fault.to_string();
+    };
+    match detect::extension_opcode(opcode) {
+        Some((platform, name)) => format!(
+            "{fault}: the {name} instruction of {platform}, which this emulator doesn't run"
+        ),
+        None => format!("{fault}: not an instruction, the rom may have jumped into its data"),
+    }
+}
+
+// Draws the display with half block characters, two rows per line,
+// so it keeps its aspect ratio in a terminal
+fn display_to_text(display: &[bool]) -> String {
+    let mut text = String::new();
+    for y in (0..DISPLAY_HEIGHT).step_by(2) {
+        for x in 0..DISPLAY_WIDTH {
+            let top = display[y * DISPLAY_WIDTH + x];
+            let bottom = display[(y + 1) * DISPLAY_WIDTH + x];
+            text.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
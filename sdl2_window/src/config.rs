@@ -0,0 +1,317 @@
+use crate::video::Scaling;
+use emulator::audio::{BuzzerConfig, Waveform};
+use emulator::database::{Database, RomInfo};
+use emulator::detect::{self, Detection};
+use emulator::keymap::KeyBindings;
+use emulator::palette::Palette;
+use emulator::phosphor::Persistence;
+use emulator::quirks::Quirks;
+use emulator::rom;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+// directory with more rom database files, in the format of the bundled one
+const DATABASE_DIR_NAME: &str = "database";
+
+pub const DEFAULT_SCALE: u32 = 15;
+// a 4096 pixels wide window, larger ones overflow or fail to open
+pub const MAX_SCALE: u32 = 64;
+// 12 instructions on each 60hz frame
+pub const DEFAULT_IPS: u32 = 720;
+
+// Settings of the emulator, from the config file or the command line.
+// Everything is optional, what isn't set falls back to the next source:
+// command line -> rom section of the config -> config -> rom database ->
+// detected platform -> default.
+//
+//   ips = 700
+//   palette = "amber"
+//   quirks = "schip"
+//
+//   [audio]
+//   wave = "triangle"
+//   volume = 0.5
+//
+//   [keys]
+//   5 = ["KeyW", "ArrowUp"]
+//
+//   [roms.0123456789abcdef0123456789abcdef01234567]
+//   ips = 1000
+//   keys = { 4 = "ArrowLeft" }
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub ips: Option<u32>,
+    pub scale: Option<u32>,
+    #[serde(default, deserialize_with = "parsed")]
+    pub scaling: Option<Scaling>,
+    #[serde(default, deserialize_with = "parsed")]
+    pub palette: Option<Palette>,
+    #[serde(default, deserialize_with = "parsed")]
+    pub persistence: Option<Persistence>,
+    #[serde(default, deserialize_with = "parsed")]
+    pub quirks: Option<Quirks>,
+    // keymap file, instead of the default one
+    pub keymap: Option<PathBuf>,
+    #[serde(default)]
+    pub audio: AudioSettings,
+    // bindings applied over the keymap file
+    #[serde(default)]
+    pub keys: KeyBindings,
+    // settings of single roms, by SHA-1, only read at the top level
+    #[serde(default)]
+    pub roms: BTreeMap<String, Settings>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AudioSettings {
+    #[serde(default, deserialize_with = "parsed")]
+    pub wave: Option<Waveform>,
+    pub freq: Option<f32>,
+    pub volume: Option<f32>,
+    pub attack: Option<f32>,
+    pub release: Option<f32>,
+    pub mute: Option<bool>,
+}
+
+impl Settings {
+    // the settings the rom database has for a rom
+    pub fn from_rom_info(info: &RomInfo) -> Settings {
+        Settings {
+            ips: info.tickrate.map(|tickrate| tickrate.saturating_mul(60)),
+            palette: info.palette,
+            quirks: info.quirks,
+            ..Settings::default()
+        }
+    }
+
+    // the quirks of the platform the rom seems written for
+    pub fn from_detection(detection: &Detection) -> Settings {
+        Settings {
+            quirks: Some(detection.quirks),
+            ..Settings::default()
+        }
+    }
+
+    // the settings for a rom, with its section applied over the general ones
+    pub fn for_rom(&self, rom_sha1: &str) -> Settings {
+        let general = Settings {
+            roms: BTreeMap::new(),
+            ..self.clone()
+        };
+        match self.roms.get(&rom_sha1.to_ascii_lowercase()) {
+            Some(rom) => general.merge(rom.clone()),
+            None => general,
+        }
+    }
+
+    // `other` over `self`, the keys are merged
+    pub fn merge(self, other: Settings) -> Settings {
+        let mut keys = self.keys;
+        keys.extend(other.keys);
+        Settings {
+            ips: other.ips.or(self.ips),
+            scale: other.scale.or(self.scale),
+            scaling: other.scaling.or(self.scaling),
+            palette: other.palette.or(self.palette),
+            persistence: other.persistence.or(self.persistence),
+            quirks: other.quirks.or(self.quirks),
+            keymap: other.keymap.or(self.keymap),
+            audio: AudioSettings {
+                wave: other.audio.wave.or(self.audio.wave),
+                freq: other.audio.freq.or(self.audio.freq),
+                volume: other.audio.volume.or(self.audio.volume),
+                attack: other.audio.attack.or(self.audio.attack),
+                release: other.audio.release.or(self.audio.release),
+                mute: other.audio.mute.or(self.audio.mute),
+            },
+            keys,
+            roms: self.roms,
+        }
+    }
+
+    pub fn ips(&self) -> u32 {
+        self.ips.unwrap_or(DEFAULT_IPS)
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale.unwrap_or(DEFAULT_SCALE)
+    }
+
+    pub fn scaling(&self) -> Scaling {
+        self.scaling.unwrap_or(Scaling::Aspect)
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette.unwrap_or(Palette::GRAY)
+    }
+
+    pub fn persistence(&self) -> Persistence {
+        self.persistence.unwrap_or_default()
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks.unwrap_or_default()
+    }
+
+    pub fn mute(&self) -> bool {
+        self.audio.mute.unwrap_or(false)
+    }
+
+    pub fn buzzer(&self) -> BuzzerConfig {
+        let default = BuzzerConfig::default();
+        BuzzerConfig {
+            waveform: self.audio.wave.unwrap_or(default.waveform),
+            frequency: self.audio.freq.unwrap_or(default.frequency),
+            volume: self.audio.volume.unwrap_or(default.volume),
+            attack: self.audio.attack.unwrap_or(default.attack),
+            release: self.audio.release.unwrap_or(default.release),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.ips == Some(0) || self.scale == Some(0) {
+            return Err("ips and scale must be greater than zero".to_string());
+        }
+        if self.scale.is_some_and(|scale| scale > MAX_SCALE) {
+            return Err(format!("the scale must be at most {MAX_SCALE}"));
+        }
+        let audio = &self.audio;
+        let numbers = [audio.freq, audio.volume, audio.attack, audio.release];
+        if numbers
+            .into_iter()
+            .flatten()
+            .any(|n| !n.is_finite() || n < 0.0)
+        {
+            return Err("the audio settings must be positive numbers".to_string());
+        }
+        if audio.freq == Some(0.0) {
+            return Err("the frequency must be greater than zero".to_string());
+        }
+        // louder than full scale would clip, the command line clamps it instead
+        if audio.volume.is_some_and(|volume| volume > 1.0) {
+            return Err("the volume must be between 0.0 and 1.0".to_string());
+        }
+        Ok(())
+    }
+}
+
+// values written as strings, parsed with FromStr like the command line options
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+// ~/.config/chip8/config.toml on linux
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("chip8").join(CONFIG_FILE_NAME))
+}
+
+// Loads the config file, a missing file at the default path is not an error
+pub fn load_config(path: Option<&Path>) -> Result<Settings, String> {
+    let (path, required) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_config_path() {
+            Some(path) => (path, false),
+            None => return Ok(Settings::default()),
+        },
+    };
+
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(error) if required || error.kind() != std::io::ErrorKind::NotFound => {
+            return Err(format!(
+                "Error reading the config {}: {error}",
+                path.display()
+            ))
+        }
+        Err(_) => return Ok(Settings::default()),
+    };
+
+    let mut settings: Settings = toml::from_str(&content)
+        .map_err(|error| format!("Error parsing the config {}: {error}", path.display()))?;
+    // the hashes are compared in lowercase
+    settings.roms = std::mem::take(&mut settings.roms)
+        .into_iter()
+        .map(|(sha1, rom)| (sha1.to_ascii_lowercase(), rom))
+        .collect();
+    let sections = std::iter::once(("", &settings))
+        .chain(settings.roms.iter().map(|(sha1, rom)| (sha1.as_str(), rom)));
+    for (sha1, section) in sections {
+        let result = if !sha1.is_empty() && !section.roms.is_empty() {
+            Err("rom sections can't have rom sections".to_string())
+        } else {
+            section.validate()
+        };
+        result.map_err(|error| {
+            let section = if sha1.is_empty() { "" } else { " in roms." };
+            format!(
+                "Error in the config {}{section}{sha1}: {error}",
+                path.display()
+            )
+        })?;
+    }
+    Ok(settings)
+}
+
+// The settings of a rom from every source, the command line first, and
+// what the rom database knows about it
+pub fn settings_for_rom(
+    rom: &[u8],
+    config_path: Option<&Path>,
+    command_line: Settings,
+) -> Result<(Settings, Option<RomInfo>), String> {
+    let rom_sha1 = rom::sha1_hex(rom);
+    let info = load_database()?.lookup(&rom_sha1).cloned();
+    let database = info
+        .as_ref()
+        .map(Settings::from_rom_info)
+        .unwrap_or_default();
+    // the detection only fills in what the database doesn't know
+    let settings = Settings::from_detection(&detect::detect(rom))
+        .merge(database)
+        .merge(load_config(config_path)?.for_rom(&rom_sha1))
+        .merge(command_line);
+    Ok((settings, info))
+}
+
+// ~/.config/chip8/database on linux
+pub fn default_database_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("chip8").join(DATABASE_DIR_NAME))
+}
+
+// The bundled rom database, extended with the .json files of the database
+// directory in alphabetical order, so later files replace earlier entries
+pub fn load_database() -> Result<Database, String> {
+    let mut database = Database::bundled();
+    let Some(dir) = default_database_dir() else {
+        return Ok(database);
+    };
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(database),
+        Err(error) => return Err(format!("Error reading {}: {error}", dir.display())),
+    };
+
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let json = std::fs::read_to_string(&path)
+            .map_err(|error| format!("Error reading {}: {error}", path.display()))?;
+        database
+            .extend_from_json(&json)
+            .map_err(|error| format!("Error in {}: {error}", path.display()))?;
+    }
+    Ok(database)
+}
@@ -0,0 +1,141 @@
+use emulator::keymap::{GamepadInput, Hotkey};
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+use std::collections::HashMap;
+
+// how far a stick or trigger must be pushed to count as pressed
+const AXIS_THRESHOLD: i16 = i16::MAX / 2;
+
+// Keeps the connected controllers open and translates their events
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    // by joystick instance id
+    controllers: HashMap<u32, GameController>,
+    // pressed state of the buttons and of the virtual buttons made from
+    // the axes, by controller
+    pressed: HashMap<(u32, &'static str), bool>,
+}
+
+impl Gamepads {
+    pub fn new(subsystem: GameControllerSubsystem) -> Self {
+        // controllers connected at startup also get a ControllerDeviceAdded
+        // event, so they are opened by the event handling
+        Self {
+            subsystem,
+            controllers: HashMap::new(),
+            pressed: HashMap::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) -> Vec<GamepadInput> {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                match self.subsystem.open(which) {
+                    Ok(controller) => {
+                        eprintln!("Controller connected: {}", controller.name());
+                        self.controllers
+                            .insert(controller.instance_id(), controller);
+                    }
+                    Err(error) => eprintln!("Error opening the controller {which}: {error}"),
+                }
+                Vec::new()
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some(controller) = self.controllers.remove(&which) {
+                    eprintln!("Controller disconnected: {}", controller.name());
+                }
+                // release whatever the controller was holding
+                let mut released = Vec::new();
+                self.pressed.retain(|(id, name), pressed| {
+                    if *id == which && *pressed {
+                        released.push(GamepadInput::Key(name, false));
+                    }
+                    *id != which
+                });
+                released
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                let mut inputs = Vec::new();
+                if let Some(name) = button_name(button) {
+                    match Hotkey::from_button(name) {
+                        Some(hotkey) => inputs.push(GamepadInput::Hotkey(hotkey)),
+                        None => self.update(which, name, true, &mut inputs),
+                    }
+                }
+                inputs
+            }
+            // the hotkeys only act on the press
+            Event::ControllerButtonUp { which, button, .. } => {
+                let mut inputs = Vec::new();
+                if let Some(name) = button_name(button) {
+                    if Hotkey::from_button(name).is_none() {
+                        self.update(which, name, false, &mut inputs);
+                    }
+                }
+                inputs
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                let (negative, positive) = axis_names(axis);
+                let mut inputs = Vec::new();
+                if let Some(name) = negative {
+                    self.update(which, name, value <= -AXIS_THRESHOLD, &mut inputs);
+                }
+                self.update(which, positive, value >= AXIS_THRESHOLD, &mut inputs);
+                inputs
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // a key input when the button of the controller changes its state
+    fn update(
+        &mut self,
+        id: u32,
+        name: &'static str,
+        pressed: bool,
+        inputs: &mut Vec<GamepadInput>,
+    ) {
+        let state = self.pressed.entry((id, name)).or_insert(false);
+        if *state != pressed {
+            *state = pressed;
+            inputs.push(GamepadInput::Key(name, pressed));
+        }
+    }
+}
+
+fn button_name(button: Button) -> Option<&'static str> {
+    match button {
+        Button::A => Some("GamepadA"),
+        Button::B => Some("GamepadB"),
+        Button::X => Some("GamepadX"),
+        Button::Y => Some("GamepadY"),
+        Button::LeftShoulder => Some("GamepadLeftShoulder"),
+        Button::RightShoulder => Some("GamepadRightShoulder"),
+        Button::DPadUp => Some("GamepadDPadUp"),
+        Button::DPadDown => Some("GamepadDPadDown"),
+        Button::DPadLeft => Some("GamepadDPadLeft"),
+        Button::DPadRight => Some("GamepadDPadRight"),
+        // reserved for the hotkeys
+        Button::Start => Some("GamepadStart"),
+        Button::Back => Some("GamepadBack"),
+        Button::LeftStick => Some("GamepadLeftStick"),
+        Button::RightStick => Some("GamepadRightStick"),
+        _ => None,
+    }
+}
+
+// names of the virtual buttons at the negative and positive side of an axis,
+// triggers only go in the positive direction. SDL axes grow down and right.
+fn axis_names(axis: Axis) -> (Option<&'static str>, &'static str) {
+    match axis {
+        Axis::LeftX => (Some("GamepadLeftStickLeft"), "GamepadLeftStickRight"),
+        Axis::LeftY => (Some("GamepadLeftStickUp"), "GamepadLeftStickDown"),
+        Axis::RightX => (Some("GamepadRightStickLeft"), "GamepadRightStickRight"),
+        Axis::RightY => (Some("GamepadRightStickUp"), "GamepadRightStickDown"),
+        Axis::TriggerLeft => (None, "GamepadLeftTrigger"),
+        Axis::TriggerRight => (None, "GamepadRightTrigger"),
+    }
+}
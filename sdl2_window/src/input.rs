@@ -0,0 +1,116 @@
+use emulator::keymap::{Keymap, KeymapConfig};
+use sdl2::keyboard::Scancode;
+use std::path::{Path, PathBuf};
+
+const KEYMAP_FILE_NAME: &str = "keymap.toml";
+
+// SDL scancodes are physical keys, like KeyboardEvent.code on the web,
+// so they are translated to the names used by the keymaps
+const SCANCODE_NAMES: [(Scancode, &str); 73] = [
+    (Scancode::A, "KeyA"),
+    (Scancode::B, "KeyB"),
+    (Scancode::C, "KeyC"),
+    (Scancode::D, "KeyD"),
+    (Scancode::E, "KeyE"),
+    (Scancode::F, "KeyF"),
+    (Scancode::G, "KeyG"),
+    (Scancode::H, "KeyH"),
+    (Scancode::I, "KeyI"),
+    (Scancode::J, "KeyJ"),
+    (Scancode::K, "KeyK"),
+    (Scancode::L, "KeyL"),
+    (Scancode::M, "KeyM"),
+    (Scancode::N, "KeyN"),
+    (Scancode::O, "KeyO"),
+    (Scancode::P, "KeyP"),
+    (Scancode::Q, "KeyQ"),
+    (Scancode::R, "KeyR"),
+    (Scancode::S, "KeyS"),
+    (Scancode::T, "KeyT"),
+    (Scancode::U, "KeyU"),
+    (Scancode::V, "KeyV"),
+    (Scancode::W, "KeyW"),
+    (Scancode::X, "KeyX"),
+    (Scancode::Y, "KeyY"),
+    (Scancode::Z, "KeyZ"),
+    (Scancode::Num0, "Digit0"),
+    (Scancode::Num1, "Digit1"),
+    (Scancode::Num2, "Digit2"),
+    (Scancode::Num3, "Digit3"),
+    (Scancode::Num4, "Digit4"),
+    (Scancode::Num5, "Digit5"),
+    (Scancode::Num6, "Digit6"),
+    (Scancode::Num7, "Digit7"),
+    (Scancode::Num8, "Digit8"),
+    (Scancode::Num9, "Digit9"),
+    (Scancode::Kp0, "Numpad0"),
+    (Scancode::Kp1, "Numpad1"),
+    (Scancode::Kp2, "Numpad2"),
+    (Scancode::Kp3, "Numpad3"),
+    (Scancode::Kp4, "Numpad4"),
+    (Scancode::Kp5, "Numpad5"),
+    (Scancode::Kp6, "Numpad6"),
+    (Scancode::Kp7, "Numpad7"),
+    (Scancode::Kp8, "Numpad8"),
+    (Scancode::Kp9, "Numpad9"),
+    (Scancode::KpPlus, "NumpadAdd"),
+    (Scancode::KpMinus, "NumpadSubtract"),
+    (Scancode::KpMultiply, "NumpadMultiply"),
+    (Scancode::KpDivide, "NumpadDivide"),
+    (Scancode::KpPeriod, "NumpadDecimal"),
+    (Scancode::KpEnter, "NumpadEnter"),
+    (Scancode::Up, "ArrowUp"),
+    (Scancode::Down, "ArrowDown"),
+    (Scancode::Left, "ArrowLeft"),
+    (Scancode::Right, "ArrowRight"),
+    (Scancode::Space, "Space"),
+    (Scancode::Return, "Enter"),
+    (Scancode::Tab, "Tab"),
+    (Scancode::Backspace, "Backspace"),
+    (Scancode::LShift, "ShiftLeft"),
+    (Scancode::RShift, "ShiftRight"),
+    (Scancode::LCtrl, "ControlLeft"),
+    (Scancode::RCtrl, "ControlRight"),
+    (Scancode::LAlt, "AltLeft"),
+    (Scancode::RAlt, "AltRight"),
+    (Scancode::Comma, "Comma"),
+    (Scancode::Period, "Period"),
+    (Scancode::Slash, "Slash"),
+    (Scancode::Semicolon, "Semicolon"),
+    (Scancode::Apostrophe, "Quote"),
+    (Scancode::LeftBracket, "BracketLeft"),
+    (Scancode::RightBracket, "BracketRight"),
+];
+
+pub fn scancode_to_hex(keymap: &Keymap, scancode: Scancode) -> Option<u8> {
+    SCANCODE_NAMES
+        .iter()
+        .find(|(code, _)| *code == scancode)
+        .and_then(|(_, name)| keymap.key(name))
+}
+
+// ~/.config/chip8/keymap.toml on linux
+pub fn default_keymap_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("chip8").join(KEYMAP_FILE_NAME))
+}
+
+// Loads the keymap file, a missing file at the default path is not an error
+pub fn load_keymap_config(path: Option<&Path>) -> Result<KeymapConfig, String> {
+    let (path, required) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_keymap_path() {
+            Some(path) => (path, false),
+            None => return Ok(KeymapConfig::default()),
+        },
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(content) => toml::from_str(&content)
+            .map_err(|error| format!("Error parsing the keymap {}: {error}", path.display())),
+        Err(error) if required || error.kind() != std::io::ErrorKind::NotFound => Err(format!(
+            "Error reading the keymap {}: {error}",
+            path.display()
+        )),
+        Err(_) => Ok(KeymapConfig::default()),
+    }
+}
@@ -0,0 +1,129 @@
+use emulator::palette::Palette;
+use emulator::{font_sprite, Emulator};
+use sdl2::rect::{Point, Rect};
+use sdl2::render::WindowCanvas;
+
+use crate::video::sdl_color;
+
+// COSMAC VIP layout, by rows
+const LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+// each key is 8x8 cells, with the 4x5 font sprite of its digit in the middle
+const KEY_CELLS: u32 = 8;
+// space around and between the keys, in cells
+const GAP_CELLS: u32 = 1;
+const PANEL_CELLS: u32 = 4 * KEY_CELLS + 5 * GAP_CELLS;
+
+// Side panel with the 16 keys of the hex keypad, showing which ones the
+// emulator sees pressed and pressing them with the mouse
+pub struct KeypadPanel {
+    pub visible: bool,
+    // key held down with the mouse
+    mouse_key: Option<u8>,
+}
+
+impl KeypadPanel {
+    pub fn new(visible: bool) -> Self {
+        Self {
+            visible,
+            mouse_key: None,
+        }
+    }
+
+    // width the window needs for the panel next to a display of the given height
+    pub fn width_for(display_height: u32) -> u32 {
+        display_height
+    }
+
+    // splits the window into the area of the display and the panel on the right,
+    // the panel is a square as tall as the window, at most a third of its width
+    pub fn layout(&self, output: (u32, u32)) -> ((u32, u32), Option<Rect>) {
+        let (width, height) = output;
+        if !self.visible {
+            return (output, None);
+        }
+        let size = height.min(width / 3).max(1);
+        let y = height.saturating_sub(size) / 2;
+        let x = width.saturating_sub(size);
+        let panel = Rect::new(x as i32, y as i32, size, size);
+        ((x, height), Some(panel))
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut WindowCanvas,
+        panel: Rect,
+        emulator: &Emulator,
+        palette: &Palette,
+    ) {
+        let background = palette.background();
+        let foreground = palette.foreground();
+        let cell = (panel.width() / PANEL_CELLS).max(1);
+
+        for (key, rect) in key_rects(panel) {
+            let pressed = emulator.is_key_pressed(key);
+            let (fill, digit) = if pressed {
+                (foreground, background)
+            } else {
+                (background.mix(foreground, 0.2), foreground)
+            };
+            canvas.set_draw_color(sdl_color(fill));
+            _ = canvas.fill_rect(rect);
+
+            // the digit is centered in the key, 4x5 pixels of `cell` size
+            canvas.set_draw_color(sdl_color(digit));
+            let x = rect.x() + (rect.width() as i32 - 4 * cell as i32) / 2;
+            let y = rect.y() + (rect.height() as i32 - 5 * cell as i32) / 2;
+            for (row, bits) in font_sprite(key).iter().enumerate() {
+                for col in 0..4 {
+                    if bits & (0x80 >> col) != 0 {
+                        let pixel = Rect::new(
+                            x + col * cell as i32,
+                            y + row as i32 * cell as i32,
+                            cell,
+                            cell,
+                        );
+                        _ = canvas.fill_rect(pixel);
+                    }
+                }
+            }
+        }
+    }
+
+    // returns the key to press, if the click was on one
+    pub fn mouse_down(&mut self, panel: Rect, x: i32, y: i32) -> Option<u8> {
+        let point = Point::new(x, y);
+        let key = key_rects(panel)
+            .find(|(_, rect)| rect.contains_point(point))
+            .map(|(key, _)| key);
+        self.mouse_key = key;
+        key
+    }
+
+    // returns the key to release
+    pub fn mouse_up(&mut self) -> Option<u8> {
+        self.mouse_key.take()
+    }
+}
+
+fn key_rects(panel: Rect) -> impl Iterator<Item = (u8, Rect)> {
+    let cell = (panel.width() / PANEL_CELLS).max(1) as i32;
+    // centers the grid, the panel is rarely a multiple of the cells
+    let offset_x = panel.x() + (panel.width() as i32 - PANEL_CELLS as i32 * cell) / 2;
+    let offset_y = panel.y() + (panel.height() as i32 - PANEL_CELLS as i32 * cell) / 2;
+    let step = (KEY_CELLS + GAP_CELLS) as i32 * cell;
+    let size = KEY_CELLS * cell as u32;
+
+    LAYOUT.into_iter().enumerate().flat_map(move |(row, keys)| {
+        keys.into_iter().enumerate().map(move |(col, key)| {
+            let x = offset_x + GAP_CELLS as i32 * cell + col as i32 * step;
+            let y = offset_y + GAP_CELLS as i32 * cell + row as i32 * step;
+            (key, Rect::new(x, y, size, size))
+        })
+    })
+}
@@ -0,0 +1,397 @@
+use crate::config::{Settings, MAX_SCALE};
+use emulator::decompile::Style;
+use std::path::PathBuf;
+
+// 5 seconds
+const DEFAULT_TEST_FRAMES: u32 = 300;
+
+pub const USAGE: &str = "\
+Usage: chip8 <command> [options]
+
+Commands:
+  run <rom>        run a rom in a window, the default when no command is given
+  info <rom>       print information about a rom and its code
+  disasm <rom>     print the instructions of a rom
+  cfg <rom>        print the control flow graph of a rom for Graphviz
+  decompile <rom>  print the code of a rom as structured pseudo-code
+  test <rom>       run a rom without a window and print the display
+
+Run 'chip8 <command> --help' for the options of a command.";
+
+pub const RUN_USAGE: &str = "\
+Usage: chip8 run <path-to-the-rom> [options]
+
+Options:
+  --ips <n>                                     instructions per second, 720 by default
+  --quirks <presets|quirks>                     default, vip (or chip8), schip, xo-chip,
+                                                and quirks to add or remove, e.g. \"schip,no-clip_sprites\"
+  --mute                                        no sound
+  --wave <square|sine|triangle|sawtooth|noise>  buzzer waveform
+  --freq <hz>                                   buzzer frequency
+  --volume <0.0-1.0>                            buzzer volume
+  --attack <seconds>                            buzzer fade in time
+  --release <seconds>                           buzzer fade out time
+  --record-audio <file.wav>                     record the buzzer into a wav file
+  --vsync                                       sync the presentation with the display
+  --frame-stats                                 show frame time statistics
+  --scale <n>                                   initial size of each chip-8 pixel, up to 64
+  --scaling <aspect|integer>                    how the display fits the window
+  --fullscreen                                  start in fullscreen, toggle with F11
+  --palette <name|colors>                       classic, gray, amber, green, lcd, octo
+                                                or hex colors, e.g. \"#000,#fff\"
+  --persistence <off|blend|frames>              reduce flicker by keeping pixels lit
+  --keymap <file.toml>                          keymap file, instead of ~/.config/chip8/keymap.toml
+  --keypad                                      show the hex keypad, clickable with the mouse, toggle with F1
+  --config <file.toml>                          config file, instead of ~/.config/chip8/config.toml
+
+The defaults of the options can be set in the config file, also for single roms.";
+
+pub const INFO_USAGE: &str = "\
+Usage: chip8 info <path-to-the-rom>
+
+Prints the size, the SHA-1 and the database entry of the rom, and what the
+code tells about it: the platform it seems written for, the instructions it
+uses, the opcodes this emulator can't run, how much of it is reachable code
+and the keys it reads. Code only reached through Bnnn counts as data.";
+
+pub const DISASM_USAGE: &str = "\
+Usage: chip8 disasm <path-to-the-rom> [options]
+
+Prints every two bytes of the rom as an instruction, with its address and opcode.
+Data mixed with the code is printed as instructions too.
+
+Options:
+  --octo  print Octo source instead, which assembles back to the same rom:
+          the reachable code as statements with labels, the rest as :byte data
+          and the rows of the sprites with their pixels in a comment";
+
+pub const CFG_USAGE: &str = "\
+Usage: chip8 cfg <path-to-the-rom>
+
+Prints the control flow graph of the code reachable from the start of the rom
+in the DOT format of Graphviz, with the subroutines as clusters:
+
+  chip8 cfg game.ch8 | dot -Tsvg > game.svg
+
+The targets of Bnnn depend on V0, so the code only reached through them is missing.";
+
+pub const DECOMPILE_USAGE: &str = "\
+Usage: chip8 decompile <path-to-the-rom> [options]
+
+Prints the code reachable from the start of the rom as pseudo-code, with a
+function for the main program and each subroutine. Skips and jumps are turned
+into if, else and loops where they follow the usual patterns, the rest is left
+as gotos. Registers used only in one way, e.g. as sprite coordinates, are named.
+
+Options:
+  --style <octo|c>  Octo like or C like, octo by default";
+
+pub const TEST_USAGE: &str = "\
+Usage: chip8 test <path-to-the-rom> [options]
+
+Runs the rom without a window or sound, as fast as possible, and prints the
+display at the end. Useful with test roms, which draw their results.
+
+Options:
+  --frames <n>               60hz frames to run, 300 by default
+  --ips <n>                  instructions per second, 720 by default
+  --quirks <presets|quirks>  same as for the run command
+  --record-audio <file.wav>  record the buzzer into a wav file, frame accurate
+  --config <file.toml>       config file, instead of ~/.config/chip8/config.toml";
+
+pub enum Command {
+    Run(Options),
+    Info(PathBuf),
+    Disasm(DisasmOptions),
+    Cfg(PathBuf),
+    Decompile(DecompileOptions),
+    Test(TestOptions),
+    // prints the usage text
+    Help(&'static str),
+}
+
+impl Command {
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.peekable();
+        let command = match args.peek().map(String::as_str) {
+            None | Some("--help" | "-h" | "help") => return Ok(Command::Help(USAGE)),
+            Some("run" | "info" | "disasm" | "cfg" | "decompile" | "test") => args.next(),
+            // chip8 <rom> [options] runs the rom
+            Some(arg) if !arg.starts_with('-') => None,
+            Some(arg) => return Err(format!("Unknown option {arg}, expected a command first.")),
+        };
+
+        let mut args = args.collect::<Vec<_>>();
+        if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+            let usage = match command.as_deref() {
+                Some("info") => INFO_USAGE,
+                Some("disasm") => DISASM_USAGE,
+                Some("cfg") => CFG_USAGE,
+                Some("decompile") => DECOMPILE_USAGE,
+                Some("test") => TEST_USAGE,
+                _ => RUN_USAGE,
+            };
+            return Ok(Command::Help(usage));
+        }
+
+        match command.as_deref() {
+            Some("info") => Ok(Command::Info(single_rom_path(&mut args)?)),
+            Some("disasm") => Ok(Command::Disasm(DisasmOptions::from_args(args.into_iter())?)),
+            Some("cfg") => Ok(Command::Cfg(single_rom_path(&mut args)?)),
+            Some("decompile") => Ok(Command::Decompile(DecompileOptions::from_args(
+                args.into_iter(),
+            )?)),
+            Some("test") => Ok(Command::Test(TestOptions::from_args(args.into_iter())?)),
+            _ => Ok(Command::Run(Options::from_args(args.into_iter())?)),
+        }
+    }
+}
+
+pub struct Options {
+    pub rom_path: PathBuf,
+    // the options that can also be set in the config file
+    pub settings: Settings,
+    pub config: Option<PathBuf>,
+    pub record_audio: Option<String>,
+    pub vsync: bool,
+    pub frame_stats: bool,
+    pub fullscreen: bool,
+    pub keypad: bool,
+}
+
+impl Options {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom_path = None;
+        let mut settings = Settings::default();
+        let mut config = None;
+        let mut record_audio = None;
+        let mut vsync = false;
+        let mut frame_stats = false;
+        let mut fullscreen = false;
+        let mut keypad = false;
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                set_rom_path(&mut rom_path, arg)?;
+                continue;
+            }
+
+            // flags without a value
+            match arg.as_str() {
+                "--mute" => {
+                    settings.audio.mute = Some(true);
+                    continue;
+                }
+                "--vsync" => {
+                    vsync = true;
+                    continue;
+                }
+                "--frame-stats" => {
+                    frame_stats = true;
+                    continue;
+                }
+                "--keypad" => {
+                    keypad = true;
+                    continue;
+                }
+                "--fullscreen" => {
+                    fullscreen = true;
+                    continue;
+                }
+                _ => (),
+            }
+
+            let Some(value) = args.next() else {
+                return Err(format!("Missing value for the option {arg}."));
+            };
+
+            match arg.as_str() {
+                "--ips" => settings.ips = Some(parse_positive(&arg, &value)?),
+                "--quirks" => settings.quirks = Some(value.parse()?),
+                "--wave" => settings.audio.wave = Some(value.parse()?),
+                "--freq" => match parse_number(&arg, &value)? {
+                    freq if freq > 0.0 => settings.audio.freq = Some(freq),
+                    _ => return Err("The frequency must be greater than zero.".to_string()),
+                },
+                "--volume" => {
+                    settings.audio.volume = Some(parse_number(&arg, &value)?.clamp(0.0, 1.0))
+                }
+                "--attack" => settings.audio.attack = Some(parse_number(&arg, &value)?),
+                "--release" => settings.audio.release = Some(parse_number(&arg, &value)?),
+                "--record-audio" => record_audio = Some(value),
+                "--scale" => match parse_positive(&arg, &value)? {
+                    scale if scale <= MAX_SCALE => settings.scale = Some(scale),
+                    _ => return Err(format!("The scale must be at most {MAX_SCALE}.")),
+                },
+                "--scaling" => settings.scaling = Some(value.parse()?),
+                "--palette" => settings.palette = Some(value.parse()?),
+                "--persistence" => settings.persistence = Some(value.parse()?),
+                "--keymap" => settings.keymap = Some(PathBuf::from(value)),
+                "--config" => config = Some(PathBuf::from(value)),
+                _ => return Err(format!("Unknown option {arg}.")),
+            }
+        }
+
+        let Some(rom_path) = rom_path else {
+            return Err("Missing rom file path.".to_string());
+        };
+
+        Ok(Self {
+            rom_path,
+            settings,
+            config,
+            record_audio,
+            vsync,
+            frame_stats,
+            fullscreen,
+            keypad,
+        })
+    }
+}
+
+pub struct TestOptions {
+    pub rom_path: PathBuf,
+    pub frames: u32,
+    // only the ips, the quirks and the buzzer are used
+    pub settings: Settings,
+    pub config: Option<PathBuf>,
+    pub record_audio: Option<String>,
+}
+
+impl TestOptions {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom_path = None;
+        let mut frames = DEFAULT_TEST_FRAMES;
+        let mut settings = Settings::default();
+        let mut config = None;
+        let mut record_audio = None;
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                set_rom_path(&mut rom_path, arg)?;
+                continue;
+            }
+
+            let Some(value) = args.next() else {
+                return Err(format!("Missing value for the option {arg}."));
+            };
+
+            match arg.as_str() {
+                "--frames" => frames = parse_positive(&arg, &value)?,
+                "--ips" => settings.ips = Some(parse_positive(&arg, &value)?),
+                "--quirks" => settings.quirks = Some(value.parse()?),
+                "--record-audio" => record_audio = Some(value),
+                "--config" => config = Some(PathBuf::from(value)),
+                _ => return Err(format!("Unknown option {arg}.")),
+            }
+        }
+
+        let Some(rom_path) = rom_path else {
+            return Err("Missing rom file path.".to_string());
+        };
+
+        Ok(Self {
+            rom_path,
+            frames,
+            settings,
+            config,
+            record_audio,
+        })
+    }
+}
+
+pub struct DisasmOptions {
+    pub rom_path: PathBuf,
+    pub octo: bool,
+}
+
+impl DisasmOptions {
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom_path = None;
+        let mut octo = false;
+
+        for arg in args {
+            match arg.as_str() {
+                "--octo" => octo = true,
+                _ if !arg.starts_with("--") => set_rom_path(&mut rom_path, arg)?,
+                _ => return Err(format!("Unknown option {arg}.")),
+            }
+        }
+
+        let Some(rom_path) = rom_path else {
+            return Err("Missing rom file path.".to_string());
+        };
+
+        Ok(Self { rom_path, octo })
+    }
+}
+
+pub struct DecompileOptions {
+    pub rom_path: PathBuf,
+    pub style: Style,
+}
+
+impl DecompileOptions {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom_path = None;
+        let mut style = Style::Octo;
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                set_rom_path(&mut rom_path, arg)?;
+                continue;
+            }
+
+            let Some(value) = args.next() else {
+                return Err(format!("Missing value for the option {arg}."));
+            };
+
+            match arg.as_str() {
+                "--style" => style = value.parse()?,
+                _ => return Err(format!("Unknown option {arg}.")),
+            }
+        }
+
+        let Some(rom_path) = rom_path else {
+            return Err("Missing rom file path.".to_string());
+        };
+
+        Ok(Self { rom_path, style })
+    }
+}
+
+// instructions to run on each 60hz frame, at least one
+pub fn ticks_per_frame(ips: u32, frame_rate: u32) -> usize {
+    ((ips + frame_rate / 2) / frame_rate).max(1) as usize
+}
+
+fn set_rom_path(rom_path: &mut Option<PathBuf>, arg: String) -> Result<(), String> {
+    if rom_path.replace(PathBuf::from(arg)).is_some() {
+        return Err("Only one rom file path is expected.".to_string());
+    }
+    Ok(())
+}
+
+// the rom path of the commands without options
+fn single_rom_path(args: &mut Vec<String>) -> Result<PathBuf, String> {
+    match args.as_slice() {
+        [path] if !path.starts_with("--") => Ok(PathBuf::from(args.remove(0))),
+        [] => Err("Missing rom file path.".to_string()),
+        [arg] => Err(format!("Unknown option {arg}.")),
+        _ => Err("Only one rom file path is expected.".to_string()),
+    }
+}
+
+fn parse_number(option: &str, value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(number) if number.is_finite() && number >= 0.0 => Ok(number),
+        _ => Err(format!("Invalid value '{value}' for the option {option}.")),
+    }
+}
+
+fn parse_positive(option: &str, value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(format!("Invalid value '{value}' for the option {option}.")),
+    }
+}
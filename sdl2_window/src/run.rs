@@ -0,0 +1,334 @@
+use crate::audio::{SoundWave, DESIRED_SPEC};
+use crate::commands;
+use crate::config;
+use crate::gamepad::Gamepads;
+use crate::input;
+use crate::keypad::KeypadPanel;
+use crate::options::{ticks_per_frame, Options};
+use crate::timing::FrameScheduler;
+use crate::video;
+use emulator::audio::Buzzer;
+use emulator::keymap::{apply_bindings, GamepadInput, Hotkey};
+use emulator::phosphor::PhosphorFilter;
+use emulator::render::{self, BYTES_PER_PIXEL};
+use emulator::rom;
+use emulator::wav::AudioRecorder;
+use emulator::*;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::video::FullscreenType;
+
+const WINDOW_TITLE: &str = "Chip-8 Emulator";
+const FRAME_RATE: u32 = 60;
+const MAX_CATCH_UP_FRAMES: u32 = 5;
+
+// Runs the rom in a window until it is closed
+pub fn run(options: Options) -> Result<(), String> {
+    let buf = commands::read_rom(&options.rom_path)?;
+    let rom_sha1 = rom::sha1_hex(&buf);
+    let (settings, rom_info) =
+        config::settings_for_rom(&buf, options.config.as_deref(), options.settings)?;
+    let palette = settings.palette();
+
+    let mut recorder = match &options.record_audio {
+        Some(path) => Some(
+            AudioRecorder::create(path, settings.buzzer(), commands::RECORD_SAMPLE_RATE)
+                .map_err(|error| format!("Error creating the audio recording file: {error}"))?,
+        ),
+        None => None,
+    };
+
+    let mut keymap = input::load_keymap_config(settings.keymap.as_deref())
+        .and_then(|config| config.keymap_for(Some(&rom_sha1)))?;
+    if let Some(info) = &rom_info {
+        info.apply_key_hints(&mut keymap);
+    }
+    apply_bindings(&mut keymap, &settings.keys)?;
+
+    let ticks_per_frame = ticks_per_frame(settings.ips(), FRAME_RATE);
+    let mut chip8 = Emulator::new();
+    chip8.set_quirks(settings.quirks());
+    chip8.load_rom(&buf)?;
+
+    let sdl_context = sdl2::init().map_err(sdl_error("initialize sdl2"))?;
+    let video_subsystem = sdl_context
+        .video()
+        .map_err(sdl_error("initialize the sdl2 video subsystem"))?;
+
+    let mut gamepads = Gamepads::new(
+        sdl_context
+            .game_controller()
+            .map_err(sdl_error("initialize the sdl2 game controller subsystem"))?,
+    );
+
+    let mut device = if settings.mute() {
+        None
+    } else {
+        let audio_subsystem = sdl_context
+            .audio()
+            .map_err(sdl_error("initialize the sdl2 audio subsystem"))?;
+        let device = audio_subsystem
+            .open_playback(None, &DESIRED_SPEC, |spec| SoundWave {
+                buzzer: Buzzer::new(settings.buzzer(), spec.freq as u32),
+            })
+            .map_err(sdl_error("open the audio device"))?;
+        // the device is always playing, the buzzer outputs silence while inactive
+        device.resume();
+        Some(device)
+    };
+
+    // nearest neighbor scaling keeps the pixels sharp
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "0");
+
+    let mut keypad = KeypadPanel::new(options.keypad);
+    let window_height = DISPLAY_HEIGHT as u32 * settings.scale();
+    let panel_width = KeypadPanel::width_for(window_height);
+    let mut window_width = DISPLAY_WIDTH as u32 * settings.scale();
+    if keypad.visible {
+        window_width += panel_width;
+    }
+    // the title of the rom when it is in the database
+    let base_title = match &rom_info {
+        Some(info) => format!("{} - {WINDOW_TITLE}", info.title),
+        None => WINDOW_TITLE.to_string(),
+    };
+    let mut window_builder = video_subsystem.window(&base_title, window_width, window_height);
+    window_builder.position_centered().resizable();
+    if options.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+    let window = window_builder
+        .build()
+        .map_err(sdl_error("create the window"))?;
+
+    let mut canvas_builder = window.into_canvas();
+    if options.vsync {
+        canvas_builder = canvas_builder.present_vsync();
+    }
+    let mut canvas = canvas_builder
+        .build()
+        .map_err(sdl_error("create a canvas from the window"))?;
+
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(
+            PixelFormatEnum::RGBA32,
+            DISPLAY_WIDTH as u32,
+            DISPLAY_HEIGHT as u32,
+        )
+        .map_err(sdl_error("create the display texture"))?;
+    let mut framebuffer = vec![0; render::rgba_buffer_size(1)];
+
+    let mut event_pump = sdl_context
+        .event_pump()
+        .map_err(sdl_error("initialize the event pump"))?;
+
+    let background_color = video::sdl_color(palette.background());
+
+    canvas.set_draw_color(background_color);
+    canvas.clear();
+    canvas.present();
+
+    let mut phosphor = PhosphorFilter::new(settings.persistence());
+    phosphor.update(&chip8.display);
+
+    let mut scheduler = FrameScheduler::new(FRAME_RATE, MAX_CATCH_UP_FRAMES);
+    let mut last_title = base_title.clone();
+    let mut paused = false;
+    // in memory save state, made and loaded with the controller hotkeys
+    let mut saved_state: Option<Emulator> = None;
+
+    // an instruction the emulator can't run ends the loop
+    let mut fault = None;
+    'main_game_loop: loop {
+        for event in event_pump.poll_iter() {
+            for input in gamepads.handle_event(&event) {
+                match input {
+                    GamepadInput::Key(name, pressed) => {
+                        if let Some(code) = keymap.key(name) {
+                            chip8.set_key_press(code, pressed);
+                        }
+                    }
+                    GamepadInput::Hotkey(Hotkey::Pause) => paused = !paused,
+                    GamepadInput::Hotkey(Hotkey::Reset) => {
+                        // reset clears the memory, so the rom must be loaded again
+                        chip8.reset();
+                        chip8.load_rom(&buf)?;
+                        phosphor.clear();
+                    }
+                    GamepadInput::Hotkey(Hotkey::SaveState) => saved_state = Some(chip8.clone()),
+                    GamepadInput::Hotkey(Hotkey::LoadState) => {
+                        if let Some(state) = &saved_state {
+                            chip8 = state.clone();
+                        }
+                    }
+                }
+            }
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'main_game_loop,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => video::toggle_fullscreen(&mut canvas),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    ..
+                } => {
+                    keypad.visible = !keypad.visible;
+                    if let Some(key) = keypad.mouse_up() {
+                        chip8.set_key_press(key, false);
+                    }
+                    // grow or shrink the window to keep the display size
+                    let window = canvas.window_mut();
+                    if window.fullscreen_state() == FullscreenType::Off {
+                        let (width, height) = window.size();
+                        let width = if keypad.visible {
+                            width + panel_width
+                        } else {
+                            width.saturating_sub(panel_width).max(1)
+                        };
+                        _ = window.set_size(width, height);
+                    }
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    let output_size = canvas
+                        .output_size()
+                        .unwrap_or((window_width, window_height));
+                    if let (_, Some(panel)) = keypad.layout(output_size) {
+                        if let Some(key) = keypad.mouse_down(panel, x, y) {
+                            chip8.set_key_press(key, true);
+                        }
+                    }
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => {
+                    if let Some(key) = keypad.mouse_up() {
+                        chip8.set_key_press(key, false);
+                    }
+                }
+                Event::KeyUp {
+                    scancode: Some(scancode),
+                    ..
+                } => {
+                    if let Some(code) = input::scancode_to_hex(&keymap, scancode) {
+                        chip8.set_key_press(code, false);
+                    }
+                }
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(code) = input::scancode_to_hex(&keymap, scancode) {
+                        chip8.set_key_press(code, true);
+                    }
+                }
+                _ => (),
+            }
+        }
+        let frames = scheduler.frames_due();
+        // while paused the frames are still consumed, so no time is emulated at once on resume
+        let frames = if paused { 0 } else { frames };
+        for _ in 0..frames {
+            // tick  the emulator
+            for _ in 0..ticks_per_frame {
+                if let Err(error) = chip8.tick() {
+                    fault = Some(error);
+                    break 'main_game_loop;
+                }
+            }
+
+            chip8.tick_delay_timer();
+            chip8.tick_sound_timer();
+            phosphor.update(&chip8.display);
+
+            if let Some(rec) = &mut recorder {
+                if let Err(error) = rec.record_frame(chip8.is_sound_playing()) {
+                    eprintln!("Error recording the audio, recording stopped: {error}");
+                    recorder = None;
+                }
+            }
+        }
+        if let Some(device) = &mut device {
+            device
+                .lock()
+                .buzzer
+                .set_active(!paused && chip8.is_sound_playing());
+        }
+
+        // the title shows the state of the emulator and the frame stats
+        let mut title = base_title.clone();
+        if paused {
+            title.push_str(" - paused");
+        } else if chip8.is_waiting_for_key() {
+            title.push_str(" - waiting for a key");
+        }
+        if options.frame_stats {
+            title.push_str(&format!(
+                " - {:.1} fps, {:.2}ms avg",
+                scheduler.stats.fps,
+                scheduler.stats.average().as_secs_f64() * 1000.0
+            ));
+        }
+        if title != last_title {
+            _ = canvas.window_mut().set_title(&title);
+            last_title = title;
+        }
+
+        // clear the background, visible around the display when the
+        // window doesn't have the same aspect ratio
+        canvas.set_draw_color(background_color);
+        canvas.clear();
+
+        // draw the pixels
+        phosphor.render_rgba(&palette, 1, &mut framebuffer);
+        _ = texture.update(None, &framebuffer, DISPLAY_WIDTH * BYTES_PER_PIXEL);
+        let output_size = canvas
+            .output_size()
+            .unwrap_or((window_width, window_height));
+        let (display_area, panel) = keypad.layout(output_size);
+        let display_size = (DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32);
+        let display_rect = video::display_rect(settings.scaling(), display_size, display_area);
+        _ = canvas.copy(&texture, None, display_rect);
+        if let Some(panel) = panel {
+            keypad.draw(&mut canvas, panel, &chip8, &palette);
+        }
+
+        canvas.present();
+        if !options.vsync {
+            scheduler.sleep_until_next_frame();
+        }
+    }
+
+    if options.frame_stats {
+        println!("{}", scheduler.stats);
+    }
+
+    if let Some(recorder) = recorder {
+        recorder
+            .finish()
+            .map_err(|error| format!("Error finishing the audio recording: {error}"))?;
+    }
+    match fault {
+        Some(error) => Err(commands::fault_error(error)),
+        None => Ok(()),
+    }
+}
+
+// maps the errors of sdl, which are plain strings or their own types
+fn sdl_error<E: std::fmt::Display>(action: &'static str) -> impl Fn(E) -> String {
+    move |error| format!("Failed to {action}: {error}")
+}
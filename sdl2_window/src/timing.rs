@@ -0,0 +1,135 @@
+use std::time::{Duration, Instant};
+
+// Fixed timestep scheduler. The real elapsed time is accumulated and
+// consumed in steps of one frame, so the emulation runs at the same rate
+// no matter how long emulating and drawing takes.
+pub struct FrameScheduler {
+    frame_duration: Duration,
+    // maximum number of frames run in a single update, after a long stall
+    // (e.g. the window being dragged) the remaining time is dropped instead
+    // of fast forwarding the game
+    max_catch_up: u32,
+    accumulator: Duration,
+    last_update: Instant,
+    pub stats: FrameStats,
+}
+
+impl FrameScheduler {
+    pub fn new(frame_rate: u32, max_catch_up: u32) -> Self {
+        Self {
+            frame_duration: Duration::from_secs(1) / frame_rate,
+            max_catch_up: max_catch_up.max(1),
+            accumulator: Duration::ZERO,
+            last_update: Instant::now(),
+            stats: FrameStats::new(),
+        }
+    }
+
+    // returns how many frames should be emulated since the last call
+    pub fn frames_due(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = now - self.last_update;
+        self.last_update = now;
+        self.accumulator += elapsed;
+
+        let mut frames = 0;
+        while self.accumulator >= self.frame_duration {
+            self.accumulator -= self.frame_duration;
+            frames += 1;
+        }
+
+        if frames > self.max_catch_up {
+            self.stats.dropped_frames += (frames - self.max_catch_up) as u64;
+            frames = self.max_catch_up;
+        }
+
+        if frames > 0 {
+            self.stats.record(now);
+        }
+        frames
+    }
+
+    // sleeps until the next frame is due, used when vsync is off
+    pub fn sleep_until_next_frame(&self) {
+        let next_frame = self.frame_duration.saturating_sub(self.accumulator);
+        let deadline = self.last_update + next_frame;
+        let now = Instant::now();
+        if deadline > now {
+            std::thread::sleep(deadline - now);
+        }
+    }
+}
+
+// Statistics about the time between updates that emulated at least one frame
+pub struct FrameStats {
+    pub frames: u64,
+    pub dropped_frames: u64,
+    pub min: Duration,
+    pub max: Duration,
+    total: Duration,
+    last_frame: Option<Instant>,
+    // frames counted since the start of the current one second window,
+    // used for the frames per second readout
+    window_start: Instant,
+    window_frames: u32,
+    pub fps: f64,
+}
+
+impl FrameStats {
+    fn new() -> Self {
+        Self {
+            frames: 0,
+            dropped_frames: 0,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+            total: Duration::ZERO,
+            last_frame: None,
+            window_start: Instant::now(),
+            window_frames: 0,
+            fps: 0.0,
+        }
+    }
+
+    fn record(&mut self, now: Instant) {
+        if let Some(last_frame) = self.last_frame {
+            let frame_time = now - last_frame;
+            self.frames += 1;
+            self.total += frame_time;
+            self.min = self.min.min(frame_time);
+            self.max = self.max.max(frame_time);
+        }
+        self.last_frame = Some(now);
+
+        self.window_frames += 1;
+        let window = now - self.window_start;
+        if window >= Duration::from_secs(1) {
+            self.fps = self.window_frames as f64 / window.as_secs_f64();
+            self.window_frames = 0;
+            self.window_start = now;
+        }
+    }
+
+    pub fn average(&self) -> Duration {
+        if self.frames == 0 {
+            return Duration::ZERO;
+        }
+        self.total.div_f64(self.frames as f64)
+    }
+}
+
+impl std::fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.frames == 0 {
+            return write!(f, "no frames presented");
+        }
+        write!(
+            f,
+            "{} frames, frame time avg {:.2}ms min {:.2}ms max {:.2}ms, {} frames dropped",
+            self.frames,
+            self.average().as_secs_f64() * 1000.0,
+            self.min.as_secs_f64() * 1000.0,
+            self.max.as_secs_f64() * 1000.0,
+            self.dropped_frames,
+        )
+    }
+}
@@ -0,0 +1,71 @@
+use emulator::palette;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use sdl2::video::FullscreenType;
+use std::str::FromStr;
+
+// how the display is fit into the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    // as big as possible keeping the 2:1 aspect ratio
+    Aspect,
+    // only whole multiples of the display size, keeps every pixel the same size
+    Integer,
+}
+
+impl FromStr for Scaling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "aspect" => Ok(Scaling::Aspect),
+            "integer" => Ok(Scaling::Integer),
+            _ => Err(format!(
+                "Unknown scaling '{s}', expected one of: aspect, integer"
+            )),
+        }
+    }
+}
+
+// area of the window where the display texture is drawn, centered
+pub fn display_rect(scaling: Scaling, display: (u32, u32), output: (u32, u32)) -> Rect {
+    let (display_width, display_height) = display;
+    let (output_width, output_height) = output;
+
+    let (width, height) = match scaling {
+        Scaling::Aspect => {
+            let scale = f64::min(
+                output_width as f64 / display_width as f64,
+                output_height as f64 / display_height as f64,
+            );
+            (
+                (display_width as f64 * scale) as u32,
+                (display_height as f64 * scale) as u32,
+            )
+        }
+        Scaling::Integer => {
+            let scale = u32::min(output_width / display_width, output_height / display_height);
+            // when the window is smaller than the display, draw it anyway
+            let scale = scale.max(1);
+            (display_width * scale, display_height * scale)
+        }
+    };
+
+    let x = (output_width as i32 - width as i32) / 2;
+    let y = (output_height as i32 - height as i32) / 2;
+    Rect::new(x, y, width.max(1), height.max(1))
+}
+
+pub fn sdl_color(color: palette::Color) -> Color {
+    Color::RGB(color.r, color.g, color.b)
+}
+
+pub fn toggle_fullscreen(canvas: &mut WindowCanvas) {
+    let window = canvas.window_mut();
+    let fullscreen = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+    _ = window.set_fullscreen(fullscreen);
+}
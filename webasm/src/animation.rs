@@ -0,0 +1,89 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use web_sys::{DedicatedWorkerGlobalScope, Window};
+
+type FrameCallback = Closure<dyn FnMut(f64)>;
+
+// A requestAnimationFrame loop calling a callback with the frame timestamp,
+// works on the page and inside a dedicated worker
+#[derive(Clone)]
+pub struct AnimationLoop {
+    inner: Rc<LoopState>,
+}
+
+struct LoopState {
+    callback: RefCell<Option<FrameCallback>>,
+    request_id: Cell<Option<i32>>,
+}
+
+impl AnimationLoop {
+    pub fn new() -> Self {
+        Self {
+            inner: Rc::new(LoopState {
+                callback: RefCell::new(None),
+                request_id: Cell::new(None),
+            }),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.inner.request_id.get().is_some()
+    }
+
+    pub fn start(&self, mut on_frame: impl FnMut(f64) + 'static) {
+        if self.is_running() {
+            return;
+        }
+
+        // the closure only keeps a weak reference, otherwise it would keep
+        // itself alive forever
+        let state = Rc::downgrade(&self.inner);
+        let callback = FrameCallback::new(move |timestamp: f64| {
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            // stopped after this frame was requested
+            if state.request_id.get().is_none() {
+                return;
+            }
+            on_frame(timestamp);
+            // on_frame may have stopped the loop
+            if state.request_id.get().is_some() {
+                state.request_id.set(request_frame(&state));
+            }
+        });
+
+        *self.inner.callback.borrow_mut() = Some(callback);
+        self.inner.request_id.set(request_frame(&self.inner));
+    }
+
+    pub fn stop(&self) {
+        if let Some(id) = self.inner.request_id.take() {
+            cancel_frame(id);
+        }
+    }
+}
+
+fn request_frame(state: &LoopState) -> Option<i32> {
+    let callback = state.callback.borrow();
+    let callback = callback.as_ref()?.as_ref().unchecked_ref();
+    // requestAnimationFrame is available both on the page and in workers
+    let global = js_sys::global();
+    if let Some(window) = global.dyn_ref::<Window>() {
+        window.request_animation_frame(callback).ok()
+    } else if let Some(worker) = global.dyn_ref::<DedicatedWorkerGlobalScope>() {
+        worker.request_animation_frame(callback).ok()
+    } else {
+        None
+    }
+}
+
+fn cancel_frame(id: i32) {
+    let global = js_sys::global();
+    if let Some(window) = global.dyn_ref::<Window>() {
+        _ = window.cancel_animation_frame(id);
+    } else if let Some(worker) = global.dyn_ref::<DedicatedWorkerGlobalScope>() {
+        _ = worker.cancel_animation_frame(id);
+    }
+}
@@ -0,0 +1,151 @@
+// Polling of the browser Gamepad API. Only gamepads with the "standard"
+// mapping are used, their inputs are named like the SDL ones so the same
+// keymap works on both ("GamepadA", "GamepadDPadUp", ...), see
+// emulator::keymap::DEFAULT_GAMEPAD_BINDINGS.
+
+use emulator::keymap::{GamepadInput, Hotkey};
+use js_sys::{Array, Object, Reflect};
+use std::collections::HashSet;
+use wasm_bindgen::prelude::*;
+use web_sys::{Gamepad, GamepadButton, GamepadMappingType};
+
+// how far a stick must be pushed to count as pressed
+const AXIS_THRESHOLD: f64 = 0.5;
+
+// buttons of the standard layout, by index
+// https://w3c.github.io/gamepad/#remapping
+const STANDARD_BUTTONS: [&str; 16] = [
+    "GamepadA",
+    "GamepadB",
+    "GamepadX",
+    "GamepadY",
+    "GamepadLeftShoulder",
+    "GamepadRightShoulder",
+    "GamepadLeftTrigger",
+    "GamepadRightTrigger",
+    "GamepadBack",
+    "GamepadStart",
+    "GamepadLeftStick",
+    "GamepadRightStick",
+    "GamepadDPadUp",
+    "GamepadDPadDown",
+    "GamepadDPadLeft",
+    "GamepadDPadRight",
+];
+
+// virtual buttons at the negative and positive side of each standard axis,
+// axes grow down and right
+const STANDARD_AXES: [(&str, &str); 4] = [
+    ("GamepadLeftStickLeft", "GamepadLeftStickRight"),
+    ("GamepadLeftStickUp", "GamepadLeftStickDown"),
+    ("GamepadRightStickLeft", "GamepadRightStickRight"),
+    ("GamepadRightStickUp", "GamepadRightStickDown"),
+];
+
+// Remembers what was pressed on the last poll, to report only the changes.
+// The inputs of every connected gamepad are merged.
+#[derive(Default)]
+pub struct GamepadState {
+    pressed: HashSet<&'static str>,
+}
+
+impl GamepadState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // inputs that changed since the last poll
+    pub fn poll(&mut self) -> Vec<GamepadInput> {
+        let pressed = pressed_inputs();
+        let mut inputs = Vec::new();
+
+        for &name in pressed.difference(&self.pressed) {
+            match Hotkey::from_button(name) {
+                Some(hotkey) => inputs.push(GamepadInput::Hotkey(hotkey)),
+                None => inputs.push(GamepadInput::Key(name, true)),
+            }
+        }
+        for &name in self.pressed.difference(&pressed) {
+            if Hotkey::from_button(name).is_none() {
+                inputs.push(GamepadInput::Key(name, false));
+            }
+        }
+
+        self.pressed = pressed;
+        inputs
+    }
+}
+
+// Polls the gamepads from the page for the worker emulator, the Gamepad API
+// isn't available inside workers. The changes are returned as the messages
+// understood by the worker, see worker.rs.
+#[wasm_bindgen]
+pub struct GamepadPoller {
+    state: GamepadState,
+}
+
+#[wasm_bindgen]
+impl GamepadPoller {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> GamepadPoller {
+        Self {
+            state: GamepadState::new(),
+        }
+    }
+
+    pub fn poll(&mut self) -> Result<Array, JsValue> {
+        let messages = Array::new();
+        for input in self.state.poll() {
+            let message = Object::new();
+            match input {
+                GamepadInput::Key(code, pressed) => {
+                    Reflect::set(&message, &"type".into(), &"key".into())?;
+                    Reflect::set(&message, &"code".into(), &code.into())?;
+                    Reflect::set(&message, &"pressed".into(), &pressed.into())?;
+                }
+                GamepadInput::Hotkey(hotkey) => {
+                    Reflect::set(&message, &"type".into(), &"hotkey".into())?;
+                    Reflect::set(&message, &"value".into(), &hotkey.name().into())?;
+                }
+            }
+            messages.push(&message);
+        }
+        Ok(messages)
+    }
+}
+
+fn pressed_inputs() -> HashSet<&'static str> {
+    let mut pressed = HashSet::new();
+    let Some(gamepads) =
+        web_sys::window().and_then(|window| window.navigator().get_gamepads().ok())
+    else {
+        return pressed;
+    };
+
+    for gamepad in gamepads.iter() {
+        // disconnected slots are null
+        let Ok(gamepad) = gamepad.dyn_into::<Gamepad>() else {
+            continue;
+        };
+        if !gamepad.connected() || gamepad.mapping() != GamepadMappingType::Standard {
+            continue;
+        }
+
+        for (button, name) in gamepad.buttons().iter().zip(STANDARD_BUTTONS) {
+            if let Ok(button) = button.dyn_into::<GamepadButton>() {
+                if button.pressed() {
+                    pressed.insert(name);
+                }
+            }
+        }
+        for (value, (negative, positive)) in gamepad.axes().iter().zip(STANDARD_AXES) {
+            let value = value.as_f64().unwrap_or(0.0);
+            if value <= -AXIS_THRESHOLD {
+                pressed.insert(negative);
+            } else if value >= AXIS_THRESHOLD {
+                pressed.insert(positive);
+            }
+        }
+    }
+    pressed
+}
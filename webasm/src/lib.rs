@@ -1,48 +1,112 @@
 #![allow(dead_code)] // for avoid wasm function being tagged as unused
 
 extern crate console_error_panic_hook;
+mod animation;
+mod gamepad;
+mod machine;
 mod web_audio;
+mod worker;
 
+use animation::AnimationLoop;
+use emulator::audio::{BuzzerConfig, Waveform};
 use emulator::*;
+use gamepad::GamepadState;
 use js_sys::Uint8Array;
+use machine::Machine;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use web_audio::WebAudio;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
 use web_sys::{
     CanvasRenderingContext2d, 
     HtmlCanvasElement,
+    ImageData,
     KeyboardEvent,
 };
 
-const PIXEL_SIZE: usize = 15;
-const CANVAS_WIDTH: u32 = (PIXEL_SIZE * DISPLAY_WIDTH) as u32;
-const CANVAS_HEIGHT: u32 = (PIXEL_SIZE * DISPLAY_HEIGHT) as u32;
-const TICKS_PER_FRAME: usize = 15;
-
-fn key_to_hex(key: KeyboardEvent) -> Option<u8> {
-    match key.key().as_str() {
-        "1" => Some(0x1),
-        "2" => Some(0x2),
-        "3" => Some(0x3),
-        "4" => Some(0xC),
-        "q" => Some(0x4),
-        "w" => Some(0x5),
-        "e" => Some(0x6),
-        "r" => Some(0xD),
-        "a" => Some(0x7),
-        "s" => Some(0x8),
-        "d" => Some(0x9),
-        "f" => Some(0xE),
-        "z" => Some(0xA),
-        "x" => Some(0x0),
-        "c" => Some(0xB),
-        "v" => Some(0xF),
-        _ => None,
+// the canvas has one pixel per chip-8 pixel, it is scaled up with css
+const CANVAS_WIDTH: u32 = DISPLAY_WIDTH as u32;
+const CANVAS_HEIGHT: u32 = DISPLAY_HEIGHT as u32;
+
+// State shared between the handler and the animation loop
+struct Frontend {
+    machine: Machine,
+    ctx: CanvasRenderingContext2d,
+    // None when the browser doesn't support the Web Audio API
+    audio: Option<WebAudio>,
+    gamepads: GamepadState,
+}
+
+impl Frontend {
+    fn draw(&mut self) -> Result<(), JsValue> {
+        let pixels = self.machine.render();
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(pixels),
+            CANVAS_WIDTH,
+            CANVAS_HEIGHT,
+        )?;
+        self.ctx.put_image_data(&image, 0.0, 0.0)
+    }
+
+    fn sync_audio(&mut self) {
+        let playing = self.machine.is_sound_playing();
+        self.set_audio_playing(playing);
+    }
+
+    fn set_audio_playing(&mut self, playing: bool) {
+        if let Some(audio) = &mut self.audio {
+            audio.set_playing(playing);
+        }
+    }
+
+    // called by the animation loop on every browser frame
+    fn on_animation_frame(&mut self, timestamp: f64) {
+        let inputs = self.gamepads.poll();
+        let changed = !inputs.is_empty();
+        for input in inputs {
+            self.machine.handle_gamepad(input);
+        }
+
+        if self.machine.advance(timestamp) > 0 || changed {
+            self.sync_audio();
+            _ = self.draw();
+        }
     }
 }
 
 #[wasm_bindgen]
 struct EmulatorHandler {
-    emulator: Emulator,
-    ctx: CanvasRenderingContext2d,
+    state: Rc<RefCell<Frontend>>,
+    animation: AnimationLoop,
+    // set when the loop was paused because the tab got hidden,
+    // so it is only resumed if it was running before
+    paused_by_visibility: Rc<Cell<bool>>,
+    visibility_listener: Closure<dyn FnMut()>,
+}
+
+impl Drop for EmulatorHandler {
+    fn drop(&mut self) {
+        self.animation.stop();
+        if let Some(document) = web_sys::window().and_then(|window| window.document()) {
+            _ = document.remove_event_listener_with_callback(
+                "visibilitychange",
+                self.visibility_listener.as_ref().unchecked_ref(),
+            );
+        }
+    }
+}
+
+fn start_loop(state: &Rc<RefCell<Frontend>>, animation: &AnimationLoop) {
+    let state = state.clone();
+    animation.start(move |timestamp| state.borrow_mut().on_animation_frame(timestamp));
+}
+
+fn pause_loop(state: &Rc<RefCell<Frontend>>, animation: &AnimationLoop) {
+    animation.stop();
+    let mut state = state.borrow_mut();
+    state.machine.pause_clock();
+    state.set_audio_playing(false);
 }
 
 #[wasm_bindgen]
@@ -61,62 +125,282 @@ impl EmulatorHandler {
             .dyn_into::<CanvasRenderingContext2d>()
             .unwrap();
 
-        Self {
-            emulator: Emulator::new(),
+        let state = Rc::new(RefCell::new(Frontend {
+            machine: Machine::new(),
             ctx,
+            audio: WebAudio::new(BuzzerConfig::default()).ok(),
+            gamepads: GamepadState::new(),
+        }));
+        let animation = AnimationLoop::new();
+        let paused_by_visibility = Rc::new(Cell::new(false));
+
+        // pause while the tab is hidden, browsers throttle it anyway
+        let visibility_listener = {
+            let (state, animation) = (state.clone(), animation.clone());
+            let paused_by_visibility = paused_by_visibility.clone();
+            Closure::<dyn FnMut()>::new(move || {
+                let Some(document) = web_sys::window().and_then(|window| window.document()) else {
+                    return;
+                };
+                if document.hidden() {
+                    if animation.is_running() {
+                        pause_loop(&state, &animation);
+                        paused_by_visibility.set(true);
+                    }
+                } else if paused_by_visibility.replace(false) {
+                    start_loop(&state, &animation);
+                }
+            })
+        };
+        if let Some(document) = web_sys::window().and_then(|window| window.document()) {
+            _ = document.add_event_listener_with_callback(
+                "visibilitychange",
+                visibility_listener.as_ref().unchecked_ref(),
+            );
+        }
+
+        Self {
+            state,
+            animation,
+            paused_by_visibility,
+            visibility_listener,
         }
     }
 
+    // the settings the rom database has for the rom are applied,
+    // the palette and ticks per frame set later go over them
     #[wasm_bindgen]
-    pub fn load_rom(&mut self, rom: &Uint8Array) {
-        self.emulator.load_rom(&rom.to_vec())
+    pub fn load_rom(&mut self, rom: &Uint8Array) -> Result<(), JsValue> {
+        // loading a rom comes from a user gesture, so audio can be resumed
+        self.resume_audio();
+        self.state
+            .borrow_mut()
+            .machine
+            .load_rom(&rom.to_vec())
+            .map_err(|e| JsValue::from_str(&e))
     }
 
     #[wasm_bindgen]
     pub fn reset(&mut self) {
-        self.emulator.reset();
+        let mut state = self.state.borrow_mut();
+        state.machine.reset();
+        state.set_audio_playing(false);
     }
-    
+
+    // runs the emulator on every animation frame, at 60 frames per second
     #[wasm_bindgen]
-    pub fn tick(&mut self) {
-        self.emulator.tick();
+    pub fn start(&mut self) {
+        self.paused_by_visibility.set(false);
+        start_loop(&self.state, &self.animation);
+    }
+
+    // stops the animation loop, keeping the emulator state
+    #[wasm_bindgen]
+    pub fn pause(&mut self) {
+        self.paused_by_visibility.set(false);
+        pause_loop(&self.state, &self.animation);
+    }
+
+    // stops the animation loop and resets the emulator
+    #[wasm_bindgen]
+    pub fn stop(&mut self) -> Result<(), JsValue> {
+        self.pause();
+        self.reset();
+        self.draw_to_canvas()
+    }
+
+    #[wasm_bindgen]
+    pub fn is_running(&self) -> bool {
+        self.animation.is_running()
+    }
+
+    // true while the rom is blocked on FX0A, for showing an indicator
+    #[wasm_bindgen]
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.state.borrow().machine.emulator.is_waiting_for_key()
+    }
+
+    // adds roms to the bundled database, from a programs.json of the
+    // chip-8 database (https://github.com/chip-8/chip-8-database)
+    #[wasm_bindgen]
+    pub fn add_database(&mut self, json: &str) -> Result<(), JsValue> {
+        self.state
+            .borrow_mut()
+            .machine
+            .add_database_json(json)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // title of the loaded rom in the database, if it is there
+    #[wasm_bindgen]
+    pub fn rom_title(&self) -> Option<String> {
+        let state = self.state.borrow();
+        state.machine.rom_info().map(|info| info.title.clone())
+    }
+
+    // how many instructions are run on each 60hz frame
+    #[wasm_bindgen]
+    pub fn set_ticks_per_frame(&mut self, ticks: usize) {
+        self.state.borrow_mut().machine.set_ticks_per_frame(ticks);
+    }
+
+    // throws on an instruction the emulator can't run
+    #[wasm_bindgen]
+    pub fn tick(&mut self) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        state
+            .machine
+            .emulator
+            .tick()
+            .map_err(|error| error.to_string().into())
     }
 
     #[wasm_bindgen]
     pub fn tick_timers(&mut self) {
-        self.emulator.tick_delay_timer();
-        if self.emulator.tick_sound_timer() {
-            web_audio::play_sound();
+        let mut state = self.state.borrow_mut();
+        state.machine.tick_timers();
+        state.sync_audio();
+    }
+
+    #[wasm_bindgen]
+    pub fn resume_audio(&self) {
+        if let Some(audio) = &self.state.borrow().audio {
+            audio.resume();
         }
     }
 
     #[wasm_bindgen]
-    pub fn draw_to_canvas(&mut self) {
-        let display = self.emulator.display;
+    pub fn set_volume(&mut self, volume: f32) -> Result<(), JsValue> {
+        self.update_buzzer(|config| config.volume = volume.clamp(0.0, 1.0))
+    }
 
-        self.ctx.set_fill_style(&JsValue::from_str("black"));
-        self.ctx.fill_rect(0f64, 0f64, CANVAS_WIDTH as f64, CANVAS_HEIGHT as f64);
+    #[wasm_bindgen]
+    pub fn volume(&self) -> f32 {
+        self.state
+            .borrow()
+            .audio
+            .as_ref()
+            .map_or(0.0, |audio| audio.config().volume)
+    }
 
-        display
-            .chunks(DISPLAY_WIDTH)
-            .enumerate()
-            .for_each(|(y, chunk)| {
-                chunk.into_iter().enumerate().for_each(|(x, pixel)| {
-                    if *pixel {
-                        let (x, y) = (x * PIXEL_SIZE, y * PIXEL_SIZE);
-                        self.ctx.set_fill_style(&JsValue::from_str("white"));
-                        let pixel_size = PIXEL_SIZE as f64;
-                        self.ctx.fill_rect(x as f64, y as f64, pixel_size, pixel_size);
-                    }
-                })
-            });
+    // one of: square, sine, triangle, sawtooth, noise
+    #[wasm_bindgen]
+    pub fn set_waveform(&mut self, waveform: &str) -> Result<(), JsValue> {
+        let waveform: Waveform = waveform
+            .parse()
+            .map_err(|e: String| JsValue::from_str(&e))?;
+        self.update_buzzer(|config| config.waveform = waveform)
     }
 
     #[wasm_bindgen]
-    pub fn handle_key_press(&mut self, key: KeyboardEvent, pressed: bool) {
-        if let Some(hex) = key_to_hex(key) {
-            self.emulator.set_key_press(hex, pressed)
+    pub fn set_frequency(&mut self, frequency: f32) -> Result<(), JsValue> {
+        if !(frequency.is_finite() && frequency > 0.0) {
+            return Err(JsValue::from_str("The frequency must be a positive number"));
         }
+        self.update_buzzer(|config| config.frequency = frequency)
     }
 
+    // attack and release are in seconds
+    #[wasm_bindgen]
+    pub fn set_envelope(&mut self, attack: f32, release: f32) -> Result<(), JsValue> {
+        self.update_buzzer(|config| {
+            config.attack = attack.max(0.0);
+            config.release = release.max(0.0);
+        })
+    }
+
+    #[wasm_bindgen]
+    pub fn set_muted(&mut self, muted: bool) {
+        if let Some(audio) = &mut self.state.borrow_mut().audio {
+            audio.set_muted(muted);
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn is_muted(&self) -> bool {
+        self.state
+            .borrow()
+            .audio
+            .as_ref()
+            .map(WebAudio::is_muted)
+            .unwrap_or(true)
+    }
+
+    #[wasm_bindgen]
+    pub fn draw_to_canvas(&mut self) -> Result<(), JsValue> {
+        self.state.borrow_mut().draw()
+    }
+
+    // view into the rendered RGBA pixels, for pages that want to draw them
+    // by themselves. It is only valid until the next call into the module.
+    #[wasm_bindgen]
+    pub fn framebuffer(&self) -> js_sys::Uint8ClampedArray {
+        let state = self.state.borrow();
+        // SAFETY: the framebuffer is never reallocated, and the view is
+        // documented to not outlive the next call into wasm
+        unsafe { js_sys::Uint8ClampedArray::view(state.machine.framebuffer()) }
+    }
+
+    // "off", "blend" or the number of frames lit pixels take to fade out
+    #[wasm_bindgen]
+    pub fn set_persistence(&mut self, persistence: &str) -> Result<(), JsValue> {
+        let persistence = persistence
+            .parse()
+            .map_err(|e: String| JsValue::from_str(&e))?;
+        self.state.borrow_mut().machine.phosphor.set_mode(persistence);
+        Ok(())
+    }
+
+    // name of a built-in palette (classic, gray, amber, green, lcd, octo)
+    // or a comma separated list of 2 or 4 hex colors, e.g "#000,#fff"
+    #[wasm_bindgen]
+    pub fn set_palette(&mut self, palette: &str) -> Result<(), JsValue> {
+        let palette = palette.parse().map_err(|e: String| JsValue::from_str(&e))?;
+        self.state.borrow_mut().machine.set_palette(palette);
+        Ok(())
+    }
+
+    fn update_buzzer(&mut self, update: impl FnOnce(&mut BuzzerConfig)) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        let Some(audio) = &mut state.audio else {
+            return Ok(());
+        };
+        let mut config = *audio.config();
+        update(&mut config);
+        audio.set_config(config)
+    }
+
+    // keys are matched by their physical position (KeyboardEvent.code)
+    #[wasm_bindgen]
+    pub fn handle_key_press(&mut self, key: KeyboardEvent, pressed: bool) {
+        self.resume_audio();
+        self.state.borrow_mut().machine.handle_key(&key.code(), pressed);
+    }
+
+    // presses the chip-8 key 0 to F, without going through the keymap,
+    // for on screen keypads like www/touch-keypad.mjs
+    #[wasm_bindgen]
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.resume_audio();
+        self.state.borrow_mut().machine.set_key(key, pressed);
+    }
+
+    // keymap as JSON, e.g { "keys": { "5": ["KeyW", "ArrowUp"] } },
+    // see emulator::keymap::KeymapConfig
+    #[wasm_bindgen]
+    pub fn set_keymap(&mut self, json: &str) -> Result<(), JsValue> {
+        self.state
+            .borrow_mut()
+            .machine
+            .set_keymap_json(json)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    // current bindings of the 16 keys as JSON, e.g { "5": ["GamepadDPadUp", "KeyW"], ... },
+    // in the format of the "keys" section of set_keymap
+    #[wasm_bindgen]
+    pub fn keymap_json(&self) -> Result<String, JsValue> {
+        let bindings = self.state.borrow().machine.keymap_bindings();
+        serde_json::to_string(&bindings).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
 }
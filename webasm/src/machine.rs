@@ -0,0 +1,256 @@
+use emulator::database::{Database, RomInfo};
+use emulator::detect;
+use emulator::keymap::{GamepadInput, Hotkey, KeyBindings, KeyList, Keymap, KeymapConfig};
+use emulator::palette::Palette;
+use emulator::phosphor::{Persistence, PhosphorFilter};
+use emulator::render;
+use emulator::rom;
+use emulator::Emulator;
+
+const DEFAULT_TICKS_PER_FRAME: usize = 15;
+const FRAME_DURATION_MS: f64 = 1000.0 / 60.0;
+// after a long stall only this many frames are run, the rest is dropped
+const MAX_CATCH_UP_FRAMES: u32 = 5;
+
+// The emulator plus everything needed to run and draw it, independent of
+// where the pixels and sound end up, so it can run on the page or in a worker
+pub struct Machine {
+    pub emulator: Emulator,
+    pub palette: Palette,
+    pub phosphor: PhosphorFilter,
+    pub ticks_per_frame: usize,
+    // chosen by the page, for the roms the database doesn't know
+    default_palette: Palette,
+    default_ticks_per_frame: usize,
+    // set by the gamepad pause hotkey, no frames are run while paused
+    pub paused: bool,
+    keymap_config: KeymapConfig,
+    keymap: Keymap,
+    // SHA-1 of the loaded rom, for the per rom settings
+    rom_sha1: Option<String>,
+    database: Database,
+    // what the database knows about the loaded rom
+    rom_info: Option<RomInfo>,
+    // kept to load it again on reset, which clears the memory
+    rom: Vec<u8>,
+    // in memory save state, made and loaded with the gamepad hotkeys
+    saved_state: Option<Emulator>,
+    framebuffer: Vec<u8>,
+    // fixed timestep state, times are in milliseconds
+    last_timestamp: Option<f64>,
+    accumulator: f64,
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Self {
+            emulator: Emulator::new(),
+            palette: Palette::default(),
+            phosphor: PhosphorFilter::new(Persistence::Off),
+            ticks_per_frame: DEFAULT_TICKS_PER_FRAME,
+            default_palette: Palette::default(),
+            default_ticks_per_frame: DEFAULT_TICKS_PER_FRAME,
+            paused: false,
+            keymap_config: KeymapConfig::default(),
+            keymap: Keymap::default(),
+            rom_sha1: None,
+            database: Database::bundled(),
+            rom_info: None,
+            rom: Vec::new(),
+            saved_state: None,
+            framebuffer: vec![0; render::rgba_buffer_size(1)],
+            last_timestamp: None,
+            accumulator: 0.0,
+        }
+    }
+
+    // loads the rom with the settings the database has for it, roms missing
+    // from it run with the quirks of the platform they seem written for
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), String> {
+        self.emulator.load_rom(rom)?;
+        let sha1 = rom::sha1_hex(rom);
+        self.rom_info = self.database.lookup(&sha1).cloned();
+        let info = self.rom_info.as_ref();
+        self.palette = info
+            .and_then(|info| info.palette)
+            .unwrap_or(self.default_palette);
+        self.ticks_per_frame = info
+            .and_then(|info| info.tickrate)
+            .map_or(self.default_ticks_per_frame, |tickrate| tickrate as usize);
+        let quirks = info
+            .and_then(|info| info.quirks)
+            .unwrap_or_else(|| detect::detect(rom).quirks);
+        self.emulator.set_quirks(quirks);
+
+        self.rom_sha1 = Some(sha1);
+        self.rom = rom.to_vec();
+        self.saved_state = None;
+        // the config was already validated when set
+        if let Ok(keymap) = self.keymap_config.keymap_for(self.rom_sha1.as_deref()) {
+            self.set_keymap(keymap);
+        }
+        Ok(())
+    }
+
+    // adds the roms of a programs.json of the chip-8 database, they are used
+    // from the next rom loaded
+    pub fn add_database_json(&mut self, json: &str) -> Result<(), String> {
+        self.database.extend_from_json(json)
+    }
+
+    pub fn rom_info(&self) -> Option<&RomInfo> {
+        self.rom_info.as_ref()
+    }
+
+    // used for the current rom and the ones not in the database
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.default_palette = palette;
+    }
+
+    pub fn set_ticks_per_frame(&mut self, ticks: usize) {
+        self.ticks_per_frame = ticks;
+        self.default_ticks_per_frame = ticks;
+    }
+
+    // the key hints of the rom bind the gamepad, over the config
+    fn set_keymap(&mut self, mut keymap: Keymap) {
+        if let Some(info) = &self.rom_info {
+            info.apply_key_hints(&mut keymap);
+        }
+        self.keymap = keymap;
+    }
+
+    // parses a keymap from JSON, in the same format as the desktop TOML:
+    // { "keys": { "5": ["KeyW", "ArrowUp"] }, "roms": { "<sha1>": { "4": "ArrowLeft" } } }
+    pub fn set_keymap_json(&mut self, json: &str) -> Result<(), String> {
+        let config: KeymapConfig =
+            serde_json::from_str(json).map_err(|e| format!("Invalid keymap: {e}"))?;
+        let keymap = config.keymap_for(self.rom_sha1.as_deref())?;
+        self.set_keymap(keymap);
+        self.keymap_config = config;
+        Ok(())
+    }
+
+    // `code` is the KeyboardEvent.code of the key
+    pub fn handle_key(&mut self, code: &str, pressed: bool) {
+        if let Some(hex) = self.keymap.key(code) {
+            self.emulator.set_key_press(hex, pressed);
+        }
+    }
+
+    // presses a chip-8 key directly, for on screen keypads
+    pub fn set_key(&mut self, hex: u8, pressed: bool) {
+        self.emulator.set_key_press(hex & 0xF, pressed);
+    }
+
+    // current bindings of every chip-8 key, in the format of the keymap config
+    pub fn keymap_bindings(&self) -> KeyBindings {
+        (0..16u8)
+            .map(|hex| {
+                let codes = self.keymap.codes_for(hex);
+                let codes = codes.into_iter().map(String::from).collect();
+                (format!("{hex:X}"), KeyList::Many(codes))
+            })
+            .collect()
+    }
+
+    pub fn handle_gamepad(&mut self, input: GamepadInput) {
+        match input {
+            GamepadInput::Key(code, pressed) => self.handle_key(code, pressed),
+            GamepadInput::Hotkey(hotkey) => self.handle_hotkey(hotkey),
+        }
+    }
+
+    pub fn handle_hotkey(&mut self, hotkey: Hotkey) {
+        match hotkey {
+            Hotkey::Pause => {
+                self.paused = !self.paused;
+                self.pause_clock();
+            }
+            Hotkey::Reset => {
+                self.reset();
+                // the rom fit when it was first loaded
+                let rom = self.rom.clone();
+                _ = self.emulator.load_rom(&rom);
+            }
+            Hotkey::SaveState => self.saved_state = Some(self.emulator.clone()),
+            Hotkey::LoadState => {
+                if let Some(state) = &self.saved_state {
+                    self.emulator = state.clone();
+                }
+            }
+        }
+    }
+
+    // the buzzer is silenced while paused
+    pub fn is_sound_playing(&self) -> bool {
+        !self.paused && self.emulator.is_sound_playing()
+    }
+
+    pub fn reset(&mut self) {
+        self.emulator.reset();
+        self.paused = false;
+        self.phosphor.clear();
+        self.pause_clock();
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.emulator.tick_delay_timer();
+        self.emulator.tick_sound_timer();
+        self.phosphor.update(&self.emulator.display);
+    }
+
+    // one 60hz frame: the instructions and a timer decrement. An instruction
+    // the emulator can't run pauses it, a reset starts the rom again.
+    pub fn run_frame(&mut self) {
+        for _ in 0..self.ticks_per_frame {
+            if let Err(error) = self.emulator.tick() {
+                web_sys::console::error_1(&error.to_string().into());
+                self.paused = true;
+                return;
+            }
+        }
+        self.tick_timers();
+    }
+
+    // runs the frames due since the last call, `timestamp` is the one given
+    // by requestAnimationFrame. Returns how many frames were run.
+    pub fn advance(&mut self, timestamp: f64) -> u32 {
+        if self.paused {
+            return 0;
+        }
+        let elapsed = match self.last_timestamp {
+            Some(last) => (timestamp - last).max(0.0),
+            None => 0.0,
+        };
+        self.last_timestamp = Some(timestamp);
+        self.accumulator += elapsed;
+
+        let mut frames = 0;
+        while self.accumulator >= FRAME_DURATION_MS {
+            self.accumulator -= FRAME_DURATION_MS;
+            if frames < MAX_CATCH_UP_FRAMES && !self.paused {
+                self.run_frame();
+                frames += 1;
+            }
+        }
+        frames
+    }
+
+    // forgets the last timestamp, so the time spent paused isn't emulated
+    pub fn pause_clock(&mut self) {
+        self.last_timestamp = None;
+        self.accumulator = 0.0;
+    }
+
+    pub fn render(&mut self) -> &[u8] {
+        self.phosphor
+            .render_rgba(&self.palette, 1, &mut self.framebuffer);
+        &self.framebuffer
+    }
+
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+}
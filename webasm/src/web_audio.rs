@@ -1,22 +1,134 @@
-
+use emulator::audio::{Buzzer, BuzzerConfig};
+use wasm_bindgen::JsValue;
+use web_sys::AudioBufferSourceNode;
 use web_sys::AudioContext;
-use web_sys::OscillatorNode;
-use web_sys::OscillatorType;
-
-fn create_new_oscillator() -> OscillatorNode {
-    let audio_context = AudioContext::new().unwrap();
-    let oscillator = audio_context.create_oscillator().unwrap();
-    oscillator.set_type(OscillatorType::Square);
-    oscillator.frequency().set_value(440.0);
-    oscillator.connect_with_audio_node(&audio_context.destination()).unwrap();
-    oscillator
+use web_sys::AudioContextState;
+use web_sys::GainNode;
+
+// A single audio graph (looped buffer -> gain -> destination) that lives for
+// the whole session. The source never stops, the buzzer is turned on
+// and off by changing the gain.
+pub struct WebAudio {
+    context: AudioContext,
+    source: AudioBufferSourceNode,
+    gain: GainNode,
+    config: BuzzerConfig,
+    muted: bool,
+    playing: bool,
 }
 
-pub fn play_sound() {
-    let oscillator = create_new_oscillator();
-    wasm_bindgen_futures::spawn_local(async move {
-        oscillator.start().unwrap();
-        gloo_timers::future::TimeoutFuture::new(100).await;
-        oscillator.stop().unwrap();
-    });
+impl WebAudio {
+    pub fn new(config: BuzzerConfig) -> Result<Self, JsValue> {
+        let context = AudioContext::new()?;
+
+        let gain = context.create_gain()?;
+        gain.gain().set_value(0.0);
+        gain.connect_with_audio_node(&context.destination())?;
+
+        let source = create_source(&context, &gain, &config)?;
+
+        Ok(Self {
+            context,
+            source,
+            gain,
+            config,
+            muted: false,
+            playing: false,
+        })
+    }
+
+    // browsers keep the context suspended until a user gesture happens,
+    // so this should be called from inside input handlers
+    pub fn resume(&self) {
+        if self.context.state() == AudioContextState::Suspended {
+            _ = self.context.resume();
+        }
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        if self.playing != playing {
+            self.playing = playing;
+            self.update_gain();
+        }
+    }
+
+    pub fn config(&self) -> &BuzzerConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: BuzzerConfig) -> Result<(), JsValue> {
+        let waveform_changed =
+            config.waveform != self.config.waveform || config.frequency != self.config.frequency;
+        self.config = config;
+
+        if waveform_changed {
+            let source = create_source(&self.context, &self.gain, &self.config)?;
+            let old_source = std::mem::replace(&mut self.source, source);
+            _ = old_source.stop();
+            old_source.disconnect()?;
+        }
+        self.update_gain();
+        Ok(())
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.update_gain();
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    fn update_gain(&self) {
+        let (value, duration) = if self.playing && !self.muted {
+            (self.config.volume, self.config.attack)
+        } else {
+            (0.0, self.config.release)
+        };
+        // a linear ramp like the envelope of the shared buzzer, which moves
+        // from silence to the full volume in the attack or release time
+        let now = self.context.current_time();
+        let param = self.gain.gain();
+        let current = param.value();
+        let distance = match self.config.volume {
+            volume if volume > 0.0 => ((value - current).abs() / volume).min(1.0),
+            _ => 0.0,
+        };
+        let end = now + (duration * distance) as f64;
+        _ = param.cancel_scheduled_values(now);
+        _ = param.set_value_at_time(current, now);
+        _ = param.linear_ramp_to_value_at_time(value, end);
+    }
+}
+
+// Renders about one second of the waveform with the shared buzzer generator
+// and loops it, so the web sounds exactly like the other frontends.
+// The volume and envelope are applied by the gain node.
+fn create_source(
+    context: &AudioContext,
+    gain: &GainNode,
+    config: &BuzzerConfig,
+) -> Result<AudioBufferSourceNode, JsValue> {
+    let sample_rate = context.sample_rate();
+
+    // use a whole number of periods so the loop point is seamless
+    let periods = config.frequency.round().max(1.0);
+    let length = ((periods * sample_rate / config.frequency).round() as u32).max(1);
+
+    let mut buzzer = Buzzer::new(*config, sample_rate as u32);
+    let mut samples = vec![0.0; length as usize];
+    samples
+        .iter_mut()
+        .for_each(|sample| *sample = buzzer.next_wave_sample());
+
+    let buffer = context.create_buffer(1, length, sample_rate)?;
+    buffer.copy_to_channel(&samples, 0)?;
+
+    let source = context.create_buffer_source()?;
+    source.set_buffer(Some(&buffer));
+    source.set_loop(true);
+    source.connect_with_audio_node(gain)?;
+    source.start()?;
+    Ok(source)
 }
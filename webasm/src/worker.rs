@@ -0,0 +1,278 @@
+// Entry points to run the emulator inside a dedicated web worker, drawing
+// into an OffscreenCanvas transferred from the page. The page talks to it
+// with postMessage, see www/worker.mjs and www/worker-client.mjs.
+//
+// Messages received by the worker, as plain objects:
+//   { type: "rom", rom: Uint8Array }
+//   { type: "key", code: string, pressed: bool }  (KeyboardEvent.code or gamepad input)
+//   { type: "hex_key", key: number, pressed: bool }  (chip-8 key 0 to 15)
+//   { type: "hotkey", value: "pause" | "reset" | "save_state" | "load_state" }
+//   { type: "keymap", value: string }  (JSON keymap)
+//   { type: "database", value: string }  (programs.json of the chip-8 database)
+//   { type: "start" } | { type: "pause" } | { type: "stop" }
+//   { type: "palette", value: string }
+//   { type: "persistence", value: string }
+//   { type: "ticks_per_frame", value: number }
+// Messages posted back to the page:
+//   { type: "sound", playing: bool }
+//   { type: "key_wait", waiting: bool }  (the rom is blocked on FX0A)
+//
+// Workers have no Web Audio, so the page plays the buzzer with `WorkerAudio`
+// from the sound messages. They have no Gamepad API either, the page polls it
+// with `GamepadPoller`.
+
+use crate::animation::AnimationLoop;
+use crate::machine::Machine;
+use crate::web_audio::WebAudio;
+use emulator::audio::BuzzerConfig;
+use emulator::keymap::Hotkey;
+use emulator::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use js_sys::{Object, Reflect, Uint8Array};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use web_sys::{
+    DedicatedWorkerGlobalScope, ImageData, OffscreenCanvas, OffscreenCanvasRenderingContext2d,
+};
+
+struct WorkerState {
+    machine: Machine,
+    ctx: OffscreenCanvasRenderingContext2d,
+    scope: DedicatedWorkerGlobalScope,
+    // last states sent to the page, to only post the changes
+    sound_playing: bool,
+    waiting_for_key: bool,
+}
+
+impl WorkerState {
+    fn draw(&mut self) -> Result<(), JsValue> {
+        let pixels = self.machine.render();
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(pixels),
+            DISPLAY_WIDTH as u32,
+            DISPLAY_HEIGHT as u32,
+        )?;
+        self.ctx.put_image_data(&image, 0.0, 0.0)
+    }
+
+    fn post_sound(&mut self, playing: bool) -> Result<(), JsValue> {
+        if self.sound_playing == playing {
+            return Ok(());
+        }
+        self.sound_playing = playing;
+
+        let message = Object::new();
+        Reflect::set(&message, &"type".into(), &"sound".into())?;
+        Reflect::set(&message, &"playing".into(), &playing.into())?;
+        self.scope.post_message(&message)
+    }
+
+    fn post_key_wait(&mut self, waiting: bool) -> Result<(), JsValue> {
+        if self.waiting_for_key == waiting {
+            return Ok(());
+        }
+        self.waiting_for_key = waiting;
+
+        let message = Object::new();
+        Reflect::set(&message, &"type".into(), &"key_wait".into())?;
+        Reflect::set(&message, &"waiting".into(), &waiting.into())?;
+        self.scope.post_message(&message)
+    }
+
+    fn on_animation_frame(&mut self, timestamp: f64) {
+        if self.machine.advance(timestamp) > 0 {
+            let playing = self.machine.is_sound_playing();
+            _ = self.post_sound(playing);
+            let waiting = self.machine.emulator.is_waiting_for_key();
+            _ = self.post_key_wait(waiting);
+            _ = self.draw();
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub struct WorkerEmulator {
+    state: Rc<RefCell<WorkerState>>,
+    animation: AnimationLoop,
+}
+
+impl Drop for WorkerEmulator {
+    fn drop(&mut self) {
+        self.animation.stop();
+    }
+}
+
+#[wasm_bindgen]
+impl WorkerEmulator {
+    // must be created inside the worker, with the canvas transferred by the page
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: OffscreenCanvas) -> Result<WorkerEmulator, JsValue> {
+        console_error_panic_hook::set_once();
+        canvas.set_width(DISPLAY_WIDTH as u32);
+        canvas.set_height(DISPLAY_HEIGHT as u32);
+
+        let ctx = canvas
+            .get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("OffscreenCanvas 2d context not available"))?
+            .dyn_into::<OffscreenCanvasRenderingContext2d>()?;
+        let scope = js_sys::global().dyn_into::<DedicatedWorkerGlobalScope>()?;
+
+        Ok(Self {
+            state: Rc::new(RefCell::new(WorkerState {
+                machine: Machine::new(),
+                ctx,
+                scope,
+                sound_playing: false,
+                waiting_for_key: false,
+            })),
+            animation: AnimationLoop::new(),
+        })
+    }
+
+    // handles the data of a message sent by the page
+    pub fn handle_message(&mut self, message: JsValue) -> Result<(), JsValue> {
+        let kind = Reflect::get(&message, &"type".into())?
+            .as_string()
+            .ok_or_else(|| JsValue::from_str("The message has no type"))?;
+        let field = |name: &str| Reflect::get(&message, &name.into());
+        let string_field = |name: &str| -> Result<String, JsValue> {
+            field(name)?
+                .as_string()
+                .ok_or_else(|| JsValue::from_str(&format!("Missing string field '{name}'")))
+        };
+
+        match kind.as_str() {
+            "rom" => {
+                let rom: Uint8Array = field("rom")?.dyn_into()?;
+                let mut state = self.state.borrow_mut();
+                state.machine.reset();
+                state
+                    .machine
+                    .load_rom(&rom.to_vec())
+                    .map_err(|e| JsValue::from_str(&e))?;
+            }
+            "key" => {
+                let pressed = field("pressed")?.is_truthy();
+                let code = string_field("code")?;
+                self.state.borrow_mut().machine.handle_key(&code, pressed);
+            }
+            "hex_key" => {
+                let pressed = field("pressed")?.is_truthy();
+                let key = field("key")?
+                    .as_f64()
+                    .ok_or_else(|| JsValue::from_str("Missing number field 'key'"))?;
+                self.state.borrow_mut().machine.set_key(key as u8, pressed);
+            }
+            "hotkey" => {
+                let hotkey: Hotkey = string_field("value")?
+                    .parse()
+                    .map_err(|e: String| JsValue::from_str(&e))?;
+                let mut state = self.state.borrow_mut();
+                state.machine.handle_hotkey(hotkey);
+                let playing = state.machine.is_sound_playing();
+                state.post_sound(playing)?;
+                state.draw()?;
+            }
+            "keymap" => {
+                let json = string_field("value")?;
+                self.state
+                    .borrow_mut()
+                    .machine
+                    .set_keymap_json(&json)
+                    .map_err(|e| JsValue::from_str(&e))?;
+            }
+            "database" => {
+                let json = string_field("value")?;
+                self.state
+                    .borrow_mut()
+                    .machine
+                    .add_database_json(&json)
+                    .map_err(|e| JsValue::from_str(&e))?;
+            }
+            "start" => {
+                let state = self.state.clone();
+                self.animation
+                    .start(move |timestamp| state.borrow_mut().on_animation_frame(timestamp));
+            }
+            "pause" => self.pause()?,
+            "stop" => {
+                self.pause()?;
+                let mut state = self.state.borrow_mut();
+                state.machine.reset();
+                state.draw()?;
+            }
+            "palette" => {
+                let palette = string_field("value")?
+                    .parse()
+                    .map_err(|e: String| JsValue::from_str(&e))?;
+                let mut state = self.state.borrow_mut();
+                state.machine.set_palette(palette);
+                state.draw()?;
+            }
+            "persistence" => {
+                let persistence = string_field("value")?
+                    .parse()
+                    .map_err(|e: String| JsValue::from_str(&e))?;
+                self.state
+                    .borrow_mut()
+                    .machine
+                    .phosphor
+                    .set_mode(persistence);
+            }
+            "ticks_per_frame" => {
+                let ticks = field("value")?
+                    .as_f64()
+                    .ok_or_else(|| JsValue::from_str("Missing number field 'value'"))?;
+                self.state
+                    .borrow_mut()
+                    .machine
+                    .set_ticks_per_frame(ticks as usize);
+            }
+            _ => return Err(JsValue::from_str(&format!("Unknown message type '{kind}'"))),
+        }
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<(), JsValue> {
+        self.animation.stop();
+        let mut state = self.state.borrow_mut();
+        state.machine.pause_clock();
+        state.post_sound(false)
+    }
+}
+
+// Page side buzzer for the worker, driven by its sound messages
+#[wasm_bindgen]
+pub struct WorkerAudio {
+    audio: WebAudio,
+}
+
+#[wasm_bindgen]
+impl WorkerAudio {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<WorkerAudio, JsValue> {
+        Ok(Self {
+            audio: WebAudio::new(BuzzerConfig::default())?,
+        })
+    }
+
+    // should be called from a user gesture, browsers keep audio suspended until then
+    pub fn resume(&self) {
+        self.audio.resume();
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.audio.set_playing(playing);
+    }
+
+    pub fn set_volume(&mut self, volume: f32) -> Result<(), JsValue> {
+        let mut config = *self.audio.config();
+        config.volume = volume.clamp(0.0, 1.0);
+        self.audio.set_config(config)
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.audio.set_muted(muted);
+    }
+}
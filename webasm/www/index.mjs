@@ -1,4 +1,5 @@
 import init, { EmulatorHandler } from "../pkg/webasm.js";
+import { TouchKeypad } from "./touch-keypad.mjs";
 
 await init();
 
@@ -7,8 +8,24 @@ const emulator = new EmulatorHandler(canvas);
 
 document.addEventListener("keydown", (e) => emulator.handle_key_press(e, true))
 document.addEventListener("keyup", (e) => emulator.handle_key_press(e, false))
+new TouchKeypad(document.getElementById("keypad"), (key, pressed) => emulator.set_key(key, pressed));
 
-let emulatorInterval;
+document.getElementById("volume").addEventListener("input", (e) => {
+    emulator.set_volume(parseFloat(e.target.value));
+});
+document.getElementById("waveform").addEventListener("change", (e) => {
+    emulator.set_waveform(e.target.value);
+});
+document.getElementById("palette").addEventListener("change", (e) => {
+    emulator.set_palette(e.target.value);
+    emulator.draw_to_canvas();
+});
+document.getElementById("persistence").addEventListener("change", (e) => {
+    emulator.set_persistence(e.target.value);
+});
+document.getElementById("mute").addEventListener("change", (e) => {
+    emulator.set_muted(e.target.checked);
+});
 
 document.getElementById("rom").addEventListener("change", (e) => {
     const file = e.target.files[0];
@@ -18,21 +35,15 @@ document.getElementById("rom").addEventListener("change", (e) => {
     reader.onload = (e) => {
         const fileData = e.target.result;
         const rom_data = new Uint8Array(fileData);
-        emulator.reset();
-        emulator.load_rom(rom_data);
-
-
-        if (emulatorInterval) {
-            clearInterval(emulatorInterval);
+        emulator.stop();
+        try {
+            emulator.load_rom(rom_data);
+        } catch (error) {
+            // a rom too large for the ram
+            console.error(error);
+            return;
         }
-
-        emulatorInterval = setInterval(() => {
-            for (let i = 0; i < 8; i++) 
-                emulator.tick();
-
-            emulator.tick_timers();
-            emulator.draw_to_canvas();
-        }, 12);
+        emulator.start();
     };
     reader.readAsArrayBuffer(file);
 });
@@ -0,0 +1,87 @@
+// On screen 4x4 hex keypad for touch screens, laid out like the COSMAC VIP.
+// Every finger is tracked on its own, so several keys can be held at once,
+// and sliding a finger moves the press to the key under it.
+//
+//   new TouchKeypad(container, (key, pressed) => emulator.set_key(key, pressed));
+const LAYOUT = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF,
+];
+
+// vibration on press, in milliseconds, where the browser supports it
+const HAPTIC_DURATION = 10;
+
+export class TouchKeypad {
+    constructor(container, onKey) {
+        this.onKey = onKey;
+        // pointer id -> chip-8 key held by it
+        this.pointers = new Map();
+        this.buttons = new Map();
+
+        this.element = document.createElement("div");
+        this.element.className = "touch-keypad";
+        for (const key of LAYOUT) {
+            const button = document.createElement("button");
+            button.type = "button";
+            button.textContent = key.toString(16).toUpperCase();
+            button.dataset.key = key;
+            this.buttons.set(key, button);
+            this.element.appendChild(button);
+        }
+        container.appendChild(this.element);
+
+        this.element.addEventListener("pointerdown", (e) => {
+            e.preventDefault();
+            // touches are captured by the element they start on, release
+            // them so sliding to another key is seen by it
+            if (e.target.hasPointerCapture?.(e.pointerId)) {
+                e.target.releasePointerCapture(e.pointerId);
+            }
+            this.move(e.pointerId, this.keyAt(e.clientX, e.clientY));
+        });
+        this.element.addEventListener("pointermove", (e) => {
+            if (this.pointers.has(e.pointerId)) {
+                this.move(e.pointerId, this.keyAt(e.clientX, e.clientY));
+            }
+        });
+        const release = (e) => this.move(e.pointerId, undefined);
+        this.element.addEventListener("pointerup", release);
+        this.element.addEventListener("pointercancel", release);
+        this.element.addEventListener("pointerleave", release);
+        // no long press menu or text selection while playing
+        this.element.addEventListener("contextmenu", (e) => e.preventDefault());
+    }
+
+    keyAt(x, y) {
+        const button = document.elementFromPoint(x, y)?.closest(".touch-keypad button");
+        return button && this.element.contains(button) ? Number(button.dataset.key) : undefined;
+    }
+
+    // moves the press of a pointer to another key, or releases it with undefined
+    move(pointerId, key) {
+        const previous = this.pointers.get(pointerId);
+        if (previous === key) return;
+
+        if (key === undefined) {
+            this.pointers.delete(pointerId);
+        } else {
+            this.pointers.set(pointerId, key);
+        }
+        if (previous !== undefined) this.update(previous);
+        if (key !== undefined) {
+            this.update(key);
+            navigator.vibrate?.(HAPTIC_DURATION);
+        }
+    }
+
+    // a key stays pressed while any pointer holds it
+    update(key) {
+        const pressed = [...this.pointers.values()].includes(key);
+        const button = this.buttons.get(key);
+        if (button.classList.contains("pressed") === pressed) return;
+        button.classList.toggle("pressed", pressed);
+        this.onKey(key, pressed);
+    }
+}
@@ -0,0 +1,122 @@
+// Page side of the worker emulator: transfers the canvas to the worker,
+// forwards keys, gamepads and roms, and plays the buzzer from the worker messages.
+//
+//   const emulator = await EmulatorWorker.create(canvas);
+//   emulator.loadRom(romData);
+import init, { GamepadPoller, WorkerAudio } from "../pkg/webasm.js";
+
+export class EmulatorWorker {
+    static async create(canvas) {
+        await init();
+        return new EmulatorWorker(canvas);
+    }
+
+    constructor(canvas) {
+        this.worker = new Worker(new URL("./worker.mjs", import.meta.url), { type: "module" });
+        try {
+            this.audio = new WorkerAudio();
+        } catch {
+            this.audio = null;
+        }
+        this.running = false;
+        // true while the rom waits for a key (FX0A)
+        this.waitingForKey = false;
+
+        const offscreen = canvas.transferControlToOffscreen();
+        this.worker.postMessage({ type: "init", canvas: offscreen }, [offscreen]);
+
+        this.worker.onmessage = (e) => {
+            switch (e.data.type) {
+                case "sound":
+                    this.audio?.set_playing(e.data.playing);
+                    break;
+                case "key_wait":
+                    this.waitingForKey = e.data.waiting;
+                    break;
+                case "error":
+                    console.error(e.data.message);
+                    break;
+            }
+        };
+
+        const onKey = (pressed) => (e) => {
+            this.audio?.resume();
+            this.worker.postMessage({ type: "key", code: e.code, pressed });
+        };
+        document.addEventListener("keydown", onKey(true));
+        document.addEventListener("keyup", onKey(false));
+
+        // workers have no Gamepad API, so the page polls it and forwards the changes
+        this.gamepads = new GamepadPoller();
+        const pollGamepads = () => {
+            for (const message of this.gamepads.poll()) {
+                this.worker.postMessage(message);
+            }
+            requestAnimationFrame(pollGamepads);
+        };
+        requestAnimationFrame(pollGamepads);
+
+        // workers have no visibility events, so the page pauses them
+        document.addEventListener("visibilitychange", () => {
+            if (!this.running) return;
+            this.worker.postMessage({ type: document.hidden ? "pause" : "start" });
+        });
+    }
+
+    loadRom(rom) {
+        this.audio?.resume();
+        this.worker.postMessage({ type: "rom", rom });
+        this.start();
+    }
+
+    start() {
+        this.running = true;
+        this.worker.postMessage({ type: "start" });
+    }
+
+    pause() {
+        this.running = false;
+        this.worker.postMessage({ type: "pause" });
+    }
+
+    stop() {
+        this.running = false;
+        this.worker.postMessage({ type: "stop" });
+    }
+
+    setPalette(value) {
+        this.worker.postMessage({ type: "palette", value });
+    }
+
+    setPersistence(value) {
+        this.worker.postMessage({ type: "persistence", value });
+    }
+
+    setTicksPerFrame(value) {
+        this.worker.postMessage({ type: "ticks_per_frame", value });
+    }
+
+    // JSON keymap, e.g. '{ "keys": { "5": ["KeyW", "ArrowUp"] } }'
+    setKeymap(json) {
+        this.worker.postMessage({ type: "keymap", value: json });
+    }
+
+    // JSON of a programs.json of the chip-8 database, used from the next rom loaded
+    addDatabase(json) {
+        this.worker.postMessage({ type: "database", value: json });
+    }
+
+    // presses the chip-8 key 0 to 15 directly, e.g. from a TouchKeypad
+    setKey(key, pressed) {
+        this.audio?.resume();
+        this.worker.postMessage({ type: "hex_key", key, pressed });
+    }
+
+    setVolume(volume) {
+        this.audio?.set_volume(volume);
+    }
+
+    setMuted(muted) {
+        this.audio?.set_muted(muted);
+    }
+}
@@ -0,0 +1,18 @@
+// Runs the emulator inside a dedicated worker, see worker-client.mjs
+import init, { WorkerEmulator } from "../pkg/webasm.js";
+
+const ready = init();
+let emulator;
+
+self.onmessage = async (e) => {
+    await ready;
+    try {
+        if (e.data.type === "init") {
+            emulator = new WorkerEmulator(e.data.canvas);
+            return;
+        }
+        emulator.handle_message(e.data);
+    } catch (error) {
+        self.postMessage({ type: "error", message: String(error) });
+    }
+};